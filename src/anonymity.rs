@@ -0,0 +1,262 @@
+use std::{net::IpAddr, str::FromStr};
+use reqwest::Url;
+use serde_json::Value;
+use crate::client::{BoxError, ProxyClient};
+
+/// Headers added by proxies that reveal the request went through a proxy.
+const PROXY_HEADERS: &[&str] = &[
+    "via",
+    "forwarded",
+    "forwarded-for",
+    "x-forwarded",
+    "x-forwarded-for",
+    "x-forwarded-host",
+    "x-real-ip",
+    "x-client-ip",
+    "x-originating-ip",
+    "x-proxy-id",
+    "client-ip",
+    "proxy-connection",
+];
+
+/// Fields in which echo services answer the ip the request came from.
+const ECHO_FIELDS: &[&str] = &["ip", "origin", "remote-addr"];
+
+/// How much of the client a proxy reveals, ordered from the least to the most anonymous.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Anonymity {
+    /// Real ip of the client is passed to the target.
+    Transparent,
+    /// Real ip is hidden, but the target can tell a proxy is used.
+    Anonymous,
+    /// Target can't tell a proxy is used.
+    Elite,
+}
+
+impl Anonymity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Anonymity::Transparent => "transparent",
+            Anonymity::Anonymous => "anonymous",
+            Anonymity::Elite => "elite",
+        }
+    }
+}
+
+impl FromStr for Anonymity {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "transparent" => Ok(Anonymity::Transparent),
+            "anonymous" => Ok(Anonymity::Anonymous),
+            "elite" => Ok(Anonymity::Elite),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Endpoint echoing request headers and the client ip.
+#[derive(Clone)]
+pub struct Judge {
+    pub url: Url,
+    pub real_ip: IpAddr,
+}
+
+impl Judge {
+    /// Requests the judge through `client` and classifies the proxy by its answer.
+    pub async fn classify(&self, client: &ProxyClient) -> Result<Anonymity, BoxError> {
+        let body = client
+            .get(&self.url)
+            .await?
+            .body
+            .ok_or("judge body failed to be read")?;
+
+        Ok(classify(&body, self.real_ip))
+    }
+}
+
+/// Asks the judge directly to find out the ip which must not leak through proxies.
+pub async fn fetch_real_ip(judge: &Url, client: &ProxyClient) -> Result<Option<IpAddr>, BoxError> {
+    let body = client.get(judge).await?.body;
+
+    Ok(body.as_deref().and_then(find_ip))
+}
+
+/// Classifies a proxy by the judge answer received through it.
+pub fn classify(body: &str, real_ip: IpAddr) -> Anonymity {
+    if tokens(body).any(|token| parse_ip(token) == Some(real_ip)) {
+        return Anonymity::Transparent;
+    }
+
+    // judges echo headers either as is (`X-Forwarded-For`) or cgi-like (`HTTP_X_FORWARDED_FOR`),
+    // only names count, `via` in a value or the page text isn't a header
+    let leaks = fields(body)
+        .iter()
+        .any(|(name, _)| PROXY_HEADERS.contains(&field_name(name).as_str()));
+
+    if leaks {
+        Anonymity::Anonymous
+    } else {
+        Anonymity::Elite
+    }
+}
+
+/// Finds the ip the request came from, either the whole text or a known echo field of it.
+///
+/// Other ips are ignored, in html a css selector like `a::bef` would pass for an ipv6.
+pub fn find_ip(text: &str) -> Option<IpAddr> {
+    let text = text.trim();
+    if let Some(ip) = parse_ip(text) {
+        return Some(ip);
+    }
+
+    fields(text)
+        .into_iter()
+        .filter(|(name, _)| ECHO_FIELDS.contains(&field_name(name).as_str()))
+        .find_map(|(_, value)| tokens(&value).find_map(parse_ip))
+}
+
+/// Named fields of an echo answer: keys of a json document, or `name: value` and `name = value` lines.
+fn fields(text: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    match serde_json::from_str(text) {
+        Ok(json) => json_fields(None, json, &mut fields),
+        Err(_) => {
+            for line in text.lines() {
+                let (name, value) = match line.find([':', '=']) {
+                    Some(idx) => (line[..idx].trim(), line[idx + 1..].trim()),
+                    None => continue,
+                };
+                let valid = !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                if valid {
+                    fields.push((name.into(), value.into()));
+                }
+            }
+        }
+    }
+
+    fields
+}
+
+#[inline]
+fn json_fields(key: Option<&str>, value: Value, fields: &mut Vec<(String, String)>) {
+    let value = match value {
+        Value::Object(object) => {
+            for (name, value) in object {
+                json_fields(Some(&name), value, fields);
+            }
+            String::new()
+        }
+        Value::Array(items) => {
+            for item in items {
+                json_fields(key, item, fields);
+            }
+            return;
+        }
+        Value::String(value) => value,
+        Value::Null => String::new(),
+        value => value.to_string(),
+    };
+
+    if let Some(key) = key {
+        fields.push((key.into(), value));
+    }
+}
+
+/// Header or variable name in the common form, `HTTP_X_FORWARDED_FOR` is `x-forwarded-for`.
+#[inline]
+fn field_name(name: &str) -> String {
+    let name = name.to_ascii_lowercase().replace('_', "-");
+    match name.strip_prefix("http-") {
+        Some(name) => name.into(),
+        None => name,
+    }
+}
+
+/// Parses an ip, ignoring the port that may follow an ipv4 address.
+#[inline]
+fn parse_ip(token: &str) -> Option<IpAddr> {
+    token.parse().ok().or_else(|| {
+        let (ip, port) = token.rsplit_once(':')?;
+        port.parse::<u16>().ok()?;
+        ip.parse::<std::net::Ipv4Addr>().ok().map(IpAddr::V4)
+    })
+}
+
+#[inline]
+fn tokens(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_ascii_hexdigit() && c != '.' && c != ':')
+        .map(|token| token.trim_matches(|c| c == '.' || c == ':'))
+        .filter(|token| !token.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_judge_answers() {
+        let real_ip = "1.2.3.4".parse().unwrap();
+        let classify = |body| classify(body, real_ip);
+
+        assert_eq!(classify("REMOTE_ADDR = 5.6.7.8"), Anonymity::Elite);
+        assert_eq!(
+            classify("REMOTE_ADDR = 5.6.7.8\nHTTP_VIA = 1.1 proxy"),
+            Anonymity::Anonymous
+        );
+        assert_eq!(
+            classify(r#"{"headers": {"X-Forwarded-For": "10.0.0.1"}}"#),
+            Anonymity::Anonymous
+        );
+        assert_eq!(
+            classify("REMOTE_ADDR = 5.6.7.8\nHTTP_X_FORWARDED_FOR = 1.2.3.4:5678"),
+            Anonymity::Transparent
+        );
+        // the ip must match as a whole, not as a part of another one
+        assert_eq!(classify("origin: 11.2.3.45"), Anonymity::Elite);
+        assert_eq!(
+            classify("User-Agent: via-client/1.0\nAccept: */*\n\ncomes via forwarded-for"),
+            Anonymity::Elite
+        );
+        assert_eq!(
+            classify(r#"{"headers": {"User-Agent": "via"}, "note": "x-forwarded-for"}"#),
+            Anonymity::Elite
+        );
+        assert_eq!(
+            classify(r#"{"headers": {"Forwarded": ["for=10.0.0.1"]}}"#),
+            Anonymity::Anonymous
+        );
+    }
+
+    #[test]
+    fn finds_echoed_ip() {
+        let ip = |ip: &str| Some(ip.parse().unwrap());
+        assert_eq!(find_ip(" 1.2.3.4\n"), ip("1.2.3.4"));
+        assert_eq!(find_ip("2001:db8::1"), ip("2001:db8::1"));
+        assert_eq!(
+            find_ip(r#"{"origin": "1.2.3.4:80, 5.6.7.8"}"#),
+            ip("1.2.3.4")
+        );
+        assert_eq!(
+            find_ip(r#"{"data": {"IP": "5.6.7.8"}, "asn": 13335}"#),
+            ip("5.6.7.8")
+        );
+        assert_eq!(
+            find_ip("HTTP_VIA = 1.1.1.1\nREMOTE_ADDR = 9.9.9.9"),
+            ip("9.9.9.9")
+        );
+        assert_eq!(find_ip("addr=2001:db8::1"), None);
+        assert_eq!(find_ip("no ips, only 1.2.3 and dead:beef"), None);
+    }
+
+    #[test]
+    fn ignores_ips_in_html() {
+        let html = "<html><head><style>\na::before { content: '1.2.3.4' }\n</style></head>\n\
+                    <body>Served by 10.0.0.1</body></html>";
+        assert_eq!(find_ip(html), None);
+    }
+}
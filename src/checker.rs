@@ -0,0 +1,411 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    net::IpAddr,
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use futures::stream::{Stream, StreamExt};
+use reqwest::Url;
+use tokio::{sync::Mutex, time};
+use crate::{
+    anonymity::{self, Anonymity, Judge},
+    client::{ConnectError, ProxyClient},
+    detect,
+    limit::RateLimiter,
+    outcome::{CheckOutcome, Rejection},
+    retry::RetryPolicy,
+    validate::Rules,
+};
+
+/// Proxy to be checked.
+#[derive(Clone, Debug)]
+pub struct ProxyEntry {
+    pub proxy: String,
+    /// Fields of structured formats which don't describe the proxy itself.
+    pub extra: BTreeMap<String, String>,
+    /// Input the proxy came from.
+    pub source: Arc<str>,
+}
+
+impl ProxyEntry {
+    /// Entry of a proxy without extra fields or a source.
+    pub fn new(proxy: impl Into<String>) -> Self {
+        ProxyEntry {
+            proxy: proxy.into(),
+            extra: BTreeMap::new(),
+            source: "".into(),
+        }
+    }
+}
+
+/// Details of a proxy that passed the check.
+#[derive(Clone, Debug)]
+pub struct Passed {
+    pub anonymity: Option<Anonymity>,
+    /// Time of establishing tcp connection with the proxy.
+    pub connect_time: Duration,
+    /// Time of receiving the target response through the proxy.
+    pub total_time: Duration,
+    /// Ip the target saw the request coming from.
+    pub exit_ip: Option<IpAddr>,
+    /// Amount of attempts used, including the successful one.
+    pub attempts: u32,
+    pub checked_at: SystemTime,
+}
+
+/// Check of a proxy with a single protocol.
+#[derive(Debug)]
+pub struct ProtocolCheck {
+    /// Proxy url with the protocol it was checked with.
+    pub proxy: String,
+    pub result: Result<Passed, Rejection>,
+}
+
+/// Result of checking a proxy entry with every protocol it answered.
+#[derive(Debug)]
+pub struct CheckResult {
+    pub entry: ProxyEntry,
+    pub checks: Vec<ProtocolCheck>,
+}
+
+impl CheckResult {
+    /// Whether the proxy passed the check with any protocol.
+    pub fn is_valid(&self) -> bool {
+        self.checks.iter().any(|check| check.result.is_ok())
+    }
+}
+
+/// Checks proxies against the target, cheap to clone and share between tasks.
+#[derive(Clone)]
+pub struct ProxyChecker {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    target: Url,
+    timeout: u64,
+    judge: Option<Judge>,
+    min_anonymity: Option<Anonymity>,
+    rules: Rules,
+    retry: RetryPolicy,
+    // exit ips of valid proxies, kept only to deduplicate by them
+    exit_ips: Option<Mutex<HashSet<IpAddr>>>,
+    limiter: Option<Arc<RateLimiter>>,
+    max_inflight: Option<NonZeroUsize>,
+}
+
+impl ProxyChecker {
+    pub fn builder(target: Url) -> ProxyCheckerBuilder {
+        ProxyCheckerBuilder {
+            target,
+            timeout: 5,
+            judge: None,
+            min_anonymity: None,
+            rules: Rules::default(),
+            retry: RetryPolicy {
+                retries: 0,
+                backoff: Duration::from_millis(500),
+            },
+            exit_ips: None,
+            rate: 0.0,
+            max_inflight: None,
+        }
+    }
+
+    pub fn target(&self) -> &Url {
+        &self.inner.target
+    }
+
+    /// Checks the proxy with its scheme, scheme-less proxies are checked
+    /// with every protocol that answered the handshake.
+    pub async fn check(&self, entry: &ProxyEntry) -> CheckResult {
+        let inner = &self.inner;
+        let candidates = if entry.proxy.contains("://") {
+            vec![entry.proxy.clone()]
+        } else {
+            let limiter = inner.limiter.as_deref();
+            detect::detect(&entry.proxy, &inner.target, inner.timeout, limiter)
+                .await
+                .into_iter()
+                .map(|protocol| protocol.url(&entry.proxy))
+                .collect()
+        };
+
+        let mut checks = Vec::new();
+        if candidates.is_empty() {
+            let message = "no protocol answered the handshake";
+            checks.push(ProtocolCheck {
+                proxy: entry.proxy.clone(),
+                result: Err(Rejection::new(CheckOutcome::NoProtocol, message)),
+            });
+        }
+        for proxy in candidates {
+            let result = self.check_protocol(&proxy).await;
+            checks.push(ProtocolCheck { proxy, result });
+        }
+
+        CheckResult {
+            entry: entry.clone(),
+            checks,
+        }
+    }
+
+    /// Checks every proxy of the stream, yielding results as they complete.
+    ///
+    /// New checks keep within the running checks cap and wait for the connection rate,
+    /// the stream is read only as fast as they are started.
+    pub fn check_stream<S>(&self, entries: S) -> impl Stream<Item = CheckResult>
+    where
+        S: Stream<Item = ProxyEntry>,
+    {
+        let max_inflight = self
+            .inner
+            .max_inflight
+            .map_or(usize::MAX, NonZeroUsize::get);
+        let limiter = self.inner.limiter.clone();
+        let checker = self.clone();
+
+        entries
+            .then(move |entry| {
+                let limiter = limiter.clone();
+                async move {
+                    // connections of the check take the tokens, it only waits for them here
+                    if let Some(limiter) = limiter {
+                        limiter.ready().await;
+                    }
+                    entry
+                }
+            })
+            .map(move |entry| {
+                let checker = checker.clone();
+                let check = {
+                    let entry = entry.clone();
+                    tokio::spawn(async move { checker.check(&entry).await })
+                };
+                async move {
+                    // a panicked check is rejected instead of taking the stream down
+                    check.await.unwrap_or_else(|err| CheckResult {
+                        checks: vec![ProtocolCheck {
+                            proxy: entry.proxy.clone(),
+                            result: Err(Rejection::new(CheckOutcome::Other, err.to_string())),
+                        }],
+                        entry,
+                    })
+                }
+            })
+            .buffer_unordered(max_inflight)
+    }
+
+    async fn check_protocol(&self, proxy: &str) -> Result<Passed, Rejection> {
+        let passed = self.check_proxy(proxy).await?;
+
+        let mut rejection = if passed.anonymity < self.inner.min_anonymity {
+            let anonymity = passed.anonymity.map_or("unknown", Anonymity::as_str);
+            let message = format!("anonymity `{}`", anonymity);
+            Rejection::new(CheckOutcome::Anonymity, message)
+        } else if !self.claim_exit_ip(passed.exit_ip).await {
+            let message = "exit ip was already claimed by another proxy";
+            Rejection::new(CheckOutcome::DuplicateExit, message)
+        } else {
+            return Ok(passed);
+        };
+        rejection.attempts = passed.attempts;
+
+        Err(rejection)
+    }
+
+    /// Whether a proxy with this exit ip is unique, unknown exit ips are always unique.
+    #[inline]
+    async fn claim_exit_ip(&self, exit_ip: Option<IpAddr>) -> bool {
+        match (&self.inner.exit_ips, exit_ip) {
+            (Some(exit_ips), Some(exit_ip)) => exit_ips.lock().await.insert(exit_ip),
+            _ => true,
+        }
+    }
+
+    #[inline]
+    async fn check_proxy(&self, proxy: &str) -> Result<Passed, Rejection> {
+        let inner = &self.inner;
+        let client = ProxyClient::new(inner.timeout, Some(proxy))
+            .map_err(|err| Rejection::error(CheckOutcome::InvalidProxy, &*err))?;
+
+        let mut attempts = 0;
+        let mut passed = loop {
+            attempts += 1;
+            self.rate_limit().await;
+            match self.check_attempt(&client).await {
+                Ok(passed) => break passed,
+                Err(rejection)
+                    if rejection.outcome.is_transient() && attempts <= inner.retry.retries =>
+                {
+                    time::delay_for(inner.retry.delay(attempts)).await;
+                }
+                Err(mut rejection) => {
+                    rejection.attempts = attempts;
+                    return Err(rejection);
+                }
+            }
+        };
+        passed.attempts = attempts;
+
+        passed.anonymity = match &inner.judge {
+            Some(judge) => {
+                self.rate_limit().await;
+                judge.classify(&client).await.ok()
+            }
+            None => None,
+        };
+
+        Ok(passed)
+    }
+
+    /// Waits for the rate of new connections to allow one more.
+    #[inline]
+    async fn rate_limit(&self) {
+        if let Some(limiter) = &self.inner.limiter {
+            limiter.acquire().await;
+        }
+    }
+
+    /// Makes a single attempt to connect the target through the proxy.
+    async fn check_attempt(&self, client: &ProxyClient) -> Result<Passed, Rejection> {
+        let inner = &self.inner;
+
+        let started = Instant::now();
+        let reply = client.get(&inner.target).await.map_err(|err| {
+            let outcome = if err.is::<ConnectError>() {
+                CheckOutcome::of_connect_error(&*err)
+            } else {
+                CheckOutcome::of_request_error(&*err)
+            };
+            Rejection::error(outcome, &*err)
+        })?;
+        let connect_time = reply.connect_time;
+        // these are answered by the proxy itself, not the target
+        if let Some(outcome) = CheckOutcome::of_proxy_status(reply.status) {
+            return Err(Rejection::new(
+                outcome,
+                format!("status `{}`", reply.status),
+            ));
+        }
+        if !inner.rules.matches_status(reply.status) {
+            let message = format!("status `{}`", reply.status);
+            return Err(Rejection::new(CheckOutcome::StatusMismatch, message));
+        }
+        if !inner.rules.matches_headers(&reply.headers) {
+            let message = "headers don't match";
+            return Err(Rejection::new(CheckOutcome::HeaderMismatch, message));
+        }
+
+        // body is read even without body rules to find out the exit ip
+        let body = reply.body;
+        if inner.rules.needs_body()
+            && !body
+                .as_deref()
+                .is_some_and(|body| inner.rules.matches_body(body))
+        {
+            return Err(Rejection::new(
+                CheckOutcome::BodyMismatch,
+                "body doesn't match",
+            ));
+        }
+        let total_time = started.elapsed();
+        let exit_ip = body.as_deref().and_then(anonymity::find_ip);
+
+        Ok(Passed {
+            anonymity: None,
+            connect_time,
+            total_time,
+            exit_ip,
+            attempts: 1,
+            checked_at: SystemTime::now(),
+        })
+    }
+}
+
+/// Builder of a `ProxyChecker`.
+pub struct ProxyCheckerBuilder {
+    target: Url,
+    timeout: u64,
+    judge: Option<Judge>,
+    min_anonymity: Option<Anonymity>,
+    rules: Rules,
+    retry: RetryPolicy,
+    exit_ips: Option<HashSet<IpAddr>>,
+    rate: f64,
+    max_inflight: Option<NonZeroUsize>,
+}
+
+impl ProxyCheckerBuilder {
+    /// Max time(secs) of connecting and of requesting the target, `5` by default.
+    pub fn timeout(mut self, timeout: u64) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Judge to classify anonymity of proxies with.
+    pub fn judge(mut self, judge: Judge) -> Self {
+        self.judge = Some(judge);
+        self
+    }
+
+    /// Rejects proxies less anonymous than `anonymity`, requires a judge.
+    pub fn min_anonymity(mut self, anonymity: Anonymity) -> Self {
+        self.min_anonymity = Some(anonymity);
+        self
+    }
+
+    /// Rules the target response must match.
+    pub fn rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// How failed checks are retried, no retries by default.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Rejects proxies with an exit ip of a proxy that passed before, including `known` ones.
+    pub fn unique_exit<I>(mut self, known: I) -> Self
+    where
+        I: IntoIterator<Item = IpAddr>,
+    {
+        self.exit_ips = Some(known.into_iter().collect());
+        self
+    }
+
+    /// New connections per second of all checks, unlimited by default or when `0`.
+    pub fn rate(mut self, rate: f64) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Max amount of running checks of `check_stream`, unlimited by default.
+    pub fn max_inflight(mut self, max: NonZeroUsize) -> Self {
+        self.max_inflight = Some(max);
+        self
+    }
+
+    pub fn build(self) -> ProxyChecker {
+        ProxyChecker {
+            inner: Arc::new(Inner {
+                target: self.target,
+                timeout: self.timeout,
+                judge: self.judge,
+                min_anonymity: self.min_anonymity,
+                rules: self.rules,
+                retry: self.retry,
+                exit_ips: self.exit_ips.map(Mutex::new),
+                limiter: if self.rate > 0.0 {
+                    Some(Arc::new(RateLimiter::new(self.rate)))
+                } else {
+                    None
+                },
+                max_inflight: self.max_inflight,
+            }),
+        }
+    }
+}
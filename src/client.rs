@@ -0,0 +1,338 @@
+use std::{
+    error::Error,
+    fmt,
+    time::{Duration, Instant},
+};
+use hyper::{client::conn, Body, Request};
+use percent_encoding::percent_decode_str;
+use reqwest::{header::HeaderMap, StatusCode, Url};
+use tokio::{
+    io::{
+        AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Error as IoError,
+        ErrorKind as IoErrorKind,
+    },
+    net::{self, TcpStream},
+    time,
+};
+use tokio_socks::{tcp::Socks5Stream, TargetAddr};
+use crate::socks4::Socks4;
+
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Http client sending requests through a proxy.
+pub enum ProxyClient {
+    Direct(reqwest::Client),
+    Tunnel(TunnelClient),
+}
+
+/// Response received through a proxy.
+pub struct Reply {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// Body of the response, `None` if it failed to be read.
+    pub body: Option<String>,
+    /// Time of connecting the proxy, zero for direct requests.
+    pub connect_time: Duration,
+}
+
+impl ProxyClient {
+    /// Builds a client sending requests through `proxy`, or directly without it.
+    pub fn new(timeout: u64, proxy: Option<&str>) -> Result<Self, BoxError> {
+        let timeout = Duration::from_secs(timeout);
+
+        match proxy {
+            Some(proxy) => Ok(ProxyClient::Tunnel(TunnelClient::new(proxy, timeout)?)),
+            None => {
+                let client = reqwest::Client::builder().timeout(timeout).build()?;
+                Ok(ProxyClient::Direct(client))
+            }
+        }
+    }
+
+    pub async fn get(&self, url: &Url) -> Result<Reply, BoxError> {
+        match self {
+            ProxyClient::Direct(client) => {
+                let resp = client.get(url.clone()).send().await?;
+
+                Ok(Reply {
+                    status: resp.status(),
+                    headers: resp.headers().clone(),
+                    body: resp.text().await.ok(),
+                    connect_time: Duration::default(),
+                })
+            }
+            ProxyClient::Tunnel(client) => client.get(url).await,
+        }
+    }
+}
+
+/// Host of the url as it is connected to, ipv6 addresses are without brackets.
+#[inline]
+pub fn connect_host(url: &Url) -> Option<&str> {
+    let host = url.host_str()?;
+    Some(host.trim_start_matches('[').trim_end_matches(']'))
+}
+
+/// Failure of connecting the proxy itself, nothing was sent through it yet.
+#[derive(Debug)]
+pub struct ConnectError(IoError);
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to connect the proxy: {}", self.0)
+    }
+}
+
+impl Error for ConnectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// How requests are tunneled through the proxy.
+enum Tunnel {
+    /// Http proxy, `https` ones are connected with tls.
+    Http {
+        tls: bool,
+        /// Value of the `Proxy-Authorization` header.
+        auth: Option<String>,
+    },
+    Socks4(Socks4),
+    Socks5 {
+        auth: Option<(String, String)>,
+        /// Socks5h: target hostname is resolved by the proxy.
+        remote_dns: bool,
+    },
+}
+
+/// Client tunneling requests through a proxy, one connection per request.
+pub struct TunnelClient {
+    host: String,
+    port: u16,
+    tunnel: Tunnel,
+    tls: tokio_tls::TlsConnector,
+    timeout: Duration,
+}
+
+/// Connection requests are sent over, whatever the proxy is.
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+impl TunnelClient {
+    fn new(proxy: &str, timeout: Duration) -> Result<Self, BoxError> {
+        let url = Url::parse(proxy)?;
+        let decode = |part: &str| percent_decode_str(part).decode_utf8_lossy().into_owned();
+        let credentials = if url.username().is_empty() {
+            None
+        } else {
+            Some((
+                decode(url.username()),
+                decode(url.password().unwrap_or_default()),
+            ))
+        };
+
+        let tunnel = match url.scheme() {
+            "http" | "https" => Tunnel::Http {
+                tls: url.scheme() == "https",
+                auth: credentials.map(|(user, password)| {
+                    format!("Basic {}", base64::encode(format!("{}:{}", user, password)))
+                }),
+            },
+            "socks4" | "socks4a" => Tunnel::Socks4(Socks4 {
+                user_id: decode(url.username()),
+                remote_dns: url.scheme() == "socks4a",
+            }),
+            "socks5" | "socks5h" => Tunnel::Socks5 {
+                auth: credentials,
+                remote_dns: url.scheme() == "socks5h",
+            },
+            scheme => return Err(format!("unsupported proxy scheme `{}`", scheme).into()),
+        };
+        // socks ports aren't known to `Url`
+        let port = match tunnel {
+            Tunnel::Http { .. } => url.port_or_known_default(),
+            _ => Some(url.port().unwrap_or(1080)),
+        };
+
+        Ok(TunnelClient {
+            host: connect_host(&url).ok_or("proxy without host")?.into(),
+            port: port.ok_or("proxy without port")?,
+            tunnel,
+            tls: native_tls::TlsConnector::new()?.into(),
+            timeout,
+        })
+    }
+
+    async fn get(&self, url: &Url) -> Result<Reply, BoxError> {
+        let started = Instant::now();
+        let connect = TcpStream::connect((self.host.as_str(), self.port));
+        let stream = match time::timeout(self.timeout, connect).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(err)) => return Err(ConnectError(err).into()),
+            Err(_) => {
+                let err = IoError::new(IoErrorKind::TimedOut, "connect timed out");
+                return Err(ConnectError(err).into());
+            }
+        };
+        let connect_time = started.elapsed();
+
+        match time::timeout(self.timeout, self.request(stream, url)).await {
+            Ok(reply) => reply.map(|reply| Reply {
+                connect_time,
+                ..reply
+            }),
+            Err(_) => Err(IoError::new(IoErrorKind::TimedOut, "request timed out").into()),
+        }
+    }
+
+    /// Sends the request over a connection to the proxy.
+    async fn request(&self, stream: TcpStream, url: &Url) -> Result<Reply, BoxError> {
+        let host = url.host_str().ok_or("url without host")?;
+        let target = connect_host(url).ok_or("url without host")?;
+        let port = url.port_or_known_default().ok_or("url without port")?;
+        let https = match url.scheme() {
+            "https" => true,
+            "http" => false,
+            scheme => return Err(format!("unsupported scheme `{}`", scheme).into()),
+        };
+
+        let path = match url.query() {
+            Some(query) => [url.path(), "?", query].concat(),
+            None => url.path().to_owned(),
+        };
+        let authority = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_owned(),
+        };
+        let mut request = Request::get(path)
+            .header("Host", authority)
+            .header("User-Agent", "proxy-find");
+
+        let stream: Box<dyn Connection> = match &self.tunnel {
+            Tunnel::Http { tls, auth } => {
+                let mut stream: Box<dyn Connection> = if *tls {
+                    Box::new(self.tls.connect(&self.host, stream).await?)
+                } else {
+                    Box::new(stream)
+                };
+
+                if https {
+                    let authority = format!("{}:{}", host, port);
+                    let status = open_tunnel(&mut stream, &authority, auth.as_deref()).await?;
+                    // refusals are answered by the proxy itself, like `407` or `502`
+                    if !status.is_success() {
+                        return Ok(Reply {
+                            status,
+                            headers: HeaderMap::new(),
+                            body: None,
+                            connect_time: Duration::default(),
+                        });
+                    }
+                } else {
+                    // plain http requests go to the proxy with the whole url
+                    let mut url = url.clone();
+                    url.set_fragment(None);
+                    request = request.uri(url.as_str());
+                    if let Some(auth) = auth {
+                        request = request.header("Proxy-Authorization", auth.as_str());
+                    }
+                }
+
+                stream
+            }
+            Tunnel::Socks4(socks4) => {
+                let mut stream = stream;
+                socks4.handshake(&mut stream, target, port).await?;
+                Box::new(stream)
+            }
+            Tunnel::Socks5 { auth, remote_dns } => {
+                let target = if *remote_dns {
+                    TargetAddr::Domain(target.into(), port)
+                } else {
+                    let addr = net::lookup_host((target, port)).await?.next();
+                    TargetAddr::Ip(addr.ok_or("target host has no address")?)
+                };
+                match auth {
+                    Some((user, password)) => Box::new(
+                        Socks5Stream::connect_with_password_and_socket(
+                            stream, target, user, password,
+                        )
+                        .await?,
+                    ),
+                    None => Box::new(Socks5Stream::connect_with_socket(stream, target).await?),
+                }
+            }
+        };
+
+        let request = request.body(Body::empty())?;
+        if https {
+            send(self.tls.connect(host, stream).await?, request).await
+        } else {
+            send(stream, request).await
+        }
+    }
+}
+
+/// Asks an http proxy to open a tunnel to `authority`, returning the status it answered with.
+async fn open_tunnel<S>(
+    stream: &mut S,
+    authority: &str,
+    auth: Option<&str>,
+) -> Result<StatusCode, IoError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some(auth) = auth {
+        request.push_str(&format!("Proxy-Authorization: {}\r\n", auth));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // nothing follows the head until the tunnel is used
+    let invalid = || IoError::new(IoErrorKind::InvalidData, "invalid CONNECT reply");
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|end| end == b"\r\n\r\n") {
+        if head.len() > 16 * 1024 {
+            return Err(invalid());
+        }
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Err(IoErrorKind::UnexpectedEof.into());
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+
+    let status_line = head.split(|&byte| byte == b'\r').next().unwrap_or_default();
+    std::str::from_utf8(status_line)
+        .ok()
+        .and_then(|line| line.strip_prefix("HTTP/1.")?.get(2..5)?.parse().ok())
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .ok_or_else(invalid)
+}
+
+/// Sends a request over an already established connection.
+async fn send<S>(stream: S, request: Request<Body>) -> Result<Reply, BoxError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = conn::handshake(stream).await?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+
+    let (parts, body) = sender.send_request(request).await?.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .ok()
+        .map(|body| String::from_utf8_lossy(&body).into_owned());
+
+    Ok(Reply {
+        status: parts.status,
+        headers: parts.headers,
+        body,
+        connect_time: Duration::default(),
+    })
+}
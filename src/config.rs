@@ -0,0 +1,637 @@
+use std::{
+    env, error::Error, fs, net::IpAddr, num::NonZeroUsize, path::Path, str::FromStr, time::Duration,
+};
+use clap::{App as ClapApp, AppSettings, Arg as ClapArg, ArgMatches, SubCommand};
+use regex::Regex;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use toml::{value::Table as TomlTable, Value as TomlValue};
+use proxy_find::{
+    anonymity::Anonymity,
+    parse::InputFormat,
+    retry::RetryPolicy,
+    validate::{self, Rules},
+};
+use crate::output::OutputFormat;
+
+/// Prefix of variables setting options.
+const ENV_PREFIX: &str = "PROXY_FIND_";
+/// Variable setting the config file path.
+pub const CONFIG_VAR: &str = "PROXY_FIND_CONFIG";
+
+/// Command line interface of the utility.
+pub fn app() -> ClapApp<'static, 'static> {
+    ClapApp::new("proxy-finder")
+    .author("q-chan")
+    .about("Simple utility for checking status of proxies by list")
+    .after_help("Options are also read from `PROXY_FIND_*` variables named after the long flags, e.g. `PROXY_FIND_MAX_INFLIGHT`, lists are comma separated. Flags override variables, which override the config file.")
+    .arg(
+        ClapArg::with_name("config")
+            .long("config")
+            .env(CONFIG_VAR)
+            .help("Config file of options in toml, or yaml by `.yaml` extension, flags override its values")
+            .global(true)
+            .takes_value(true),
+    )
+    .subcommand(
+        SubCommand::with_name("config")
+            .about("Inspects the configuration")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("dump")
+                    .about("Prints the effective configuration, merged from the config file and flags"),
+            ),
+    )
+    .arg(
+        ClapArg::with_name("target")
+            .long("target")
+            .short("h")
+            .help("Target ip or website to test if proxy can connect other ips (`https://ifconfig.me` by default)")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("output")
+            .long("output")
+            .short("o")
+            .help("Output path of file where valid proxies will be saved (`valid.txt` by default)")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("output_format")
+            .long("output-format")
+            .help("Format of the output, structured ones have all the metadata of proxies")
+            .possible_values(&["txt", "json", "jsonl", "csv"])
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("failed_output")
+            .long("failed-output")
+            .help("Output path of file where failed proxies will be saved with failure reasons")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("report")
+            .long("report")
+            .help("Output path of json file where statistics of the run will be saved")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("input")
+            .long("input")
+            .short("i")
+            .help("Input file, directory or glob pattern of proxy lists (`-` for stdin)")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("input_format")
+            .long("input-format")
+            .help("Format of input lists, `auto` picks it by file extension and line contents")
+            .possible_values(&["auto", "plain", "colon-auth", "csv", "json", "jsonl", "extract"])
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("sources")
+            .long("sources")
+            .help("File of proxy list urls to fetch, as `<url> [plain | regex <pattern> | json <path>]` per line")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("sources_proxy")
+            .long("sources-proxy")
+            .help("Proxy to fetch proxy lists through")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("state")
+            .long("state")
+            .help("Path of file recording checked proxies to resume the run from, appends to output")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("cons_per_sec")
+            .long("cps")
+            .help("Amount of connections per seconds (0 for unlimited)")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("max_inflight")
+            .long("max-inflight")
+            .help("Max amount of checks running at the same time")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("cores")
+            .long("cores")
+            .short("c")
+            .help("Amount of cores used")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("timeout")
+            .long("timeout")
+            .short("m")
+            .help("Max timeout(secs) of request")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("deadline")
+            .long("deadline")
+            .short("d")
+            .help("Max time(secs) to wait for running checks after the last one was started")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("grace")
+            .long("grace")
+            .help("Max time(secs) to wait for running checks after interruption")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("retries")
+            .long("retries")
+            .short("r")
+            .help("Amount of retries on transient failures like timeouts or connection resets")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("backoff")
+            .long("backoff")
+            .help("Delay(millis) before the first retry, doubled for every next one up to a minute")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("judge")
+            .long("judge")
+            .short("j")
+            .help("Url of judge echoing request headers and client ip to classify anonymity")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("real_ip")
+            .long("real-ip")
+            .help("Real ip which must not leak through proxies (asked from judge by default)")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("min_anonymity")
+            .long("min-anonymity")
+            .help("Drop proxies below this anonymity level")
+            .possible_values(&["transparent", "anonymous", "elite"])
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("sort")
+            .long("sort")
+            .short("s")
+            .help("Sort saved proxies by response time, fastest first")
+            .overrides_with("no_sort"),
+    )
+    .arg(
+        ClapArg::with_name("no_sort")
+            .long("no-sort")
+            .help("Save proxies in the order they were checked, overrides `sort` of the config")
+            .overrides_with("sort"),
+    )
+    .arg(
+        ClapArg::with_name("unique_exit")
+            .long("unique-exit")
+            .short("u")
+            .help("Save only one proxy per unique exit ip")
+            .overrides_with("no_unique_exit"),
+    )
+    .arg(
+        ClapArg::with_name("no_unique_exit")
+            .long("no-unique-exit")
+            .help("Save proxies sharing exit ips, overrides `unique-exit` of the config")
+            .overrides_with("unique_exit"),
+    )
+    .arg(
+        ClapArg::with_name("status")
+            .long("status")
+            .help(
+                "Accepted status codes of target response, e.g. `200,300-399` (any by default)",
+            )
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("body_contains")
+            .long("body-contains")
+            .help("Text the target response body must contain")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("body_regex")
+            .long("body-regex")
+            .help("Regex the target response body must match")
+            .takes_value(true),
+    )
+    .arg(
+        ClapArg::with_name("header")
+            .long("header")
+            .help("Header the target response must have, as `Name` or `Name: value`")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+    )
+}
+
+/// Settings of a run.
+pub struct AppConfig {
+    pub target: Url,
+    pub input: Vec<Box<str>>,
+    pub input_format: InputFormat,
+    pub sources: Option<Box<str>>,
+    pub sources_proxy: Option<Box<str>>,
+    pub output: Box<str>,
+    pub output_format: OutputFormat,
+    pub failed_output: Option<Box<str>>,
+    pub report: Option<Box<str>>,
+    pub state: Option<Box<str>>,
+    pub cores: usize,
+    pub cons_per_sec: f64,
+    pub max_inflight: Option<NonZeroUsize>,
+    pub timeout: u64,
+    pub deadline: Option<u64>,
+    pub grace: u64,
+    pub judge: Option<Url>,
+    pub real_ip: Option<IpAddr>,
+    pub min_anonymity: Option<Anonymity>,
+    pub sort: bool,
+    pub rules: Rules,
+    pub unique_exit: bool,
+    pub retry: RetryPolicy,
+}
+
+impl AppConfig {
+    pub fn new(options: Options) -> Result<Self, Box<dyn Error>> {
+        // defaults fill every option having one
+        let options = Options::defaults().merge(options);
+
+        let input = options.input.unwrap_or_default();
+        if input.is_empty() && options.sources.is_none() {
+            return Err("Nothing to check, set `input` or `sources`".into());
+        }
+        if options.judge.is_none() && (options.real_ip.is_some() || options.min_anonymity.is_some())
+        {
+            return Err("`real-ip` and `min-anonymity` require `judge`".into());
+        }
+        if options.cores == Some(0) {
+            return Err("Invalid amount of cores `0`, must be at least `1`".into());
+        }
+        if let Some(cps) = options.cps.filter(|cps| !(cps.is_finite() && *cps >= 0.0)) {
+            return Err(format!(
+                "Invalid amount of connections `{}`, must be a finite number from `0`",
+                cps
+            )
+            .into());
+        }
+        let max_inflight = match options.max_inflight {
+            Some(max) => Some(
+                NonZeroUsize::new(max)
+                    .ok_or("Invalid max amount of running checks `0`, must be at least `1`")?,
+            ),
+            None => None,
+        };
+
+        Ok(AppConfig {
+            target: parse("target", &options.target.unwrap())?,
+            input: input.into_iter().map(Into::into).collect(),
+            input_format: parse("input format", &options.input_format.unwrap())?,
+            sources: options.sources.map(Into::into),
+            sources_proxy: options.sources_proxy.map(Into::into),
+            output: options.output.unwrap().into(),
+            output_format: parse("output format", &options.output_format.unwrap())?,
+            failed_output: options.failed_output.map(Into::into),
+            report: options.report.map(Into::into),
+            state: options.state.map(Into::into),
+            cores: options.cores.unwrap(),
+            cons_per_sec: options.cps.unwrap(),
+            max_inflight,
+            timeout: options.timeout.unwrap(),
+            deadline: options.deadline,
+            grace: options.grace.unwrap(),
+            judge: options.judge.map(|x| parse("judge", &x)).transpose()?,
+            real_ip: options.real_ip.map(|x| parse("real ip", &x)).transpose()?,
+            min_anonymity: options
+                .min_anonymity
+                .map(|x| parse("anonymity level", &x))
+                .transpose()?,
+            sort: options.sort.unwrap(),
+            rules: Rules {
+                statuses: options
+                    .status
+                    .map(|x| validate::parse_statuses(&x))
+                    .transpose()
+                    .map_err(|err| format!("Invalid status codes: {}", err))?
+                    .unwrap_or_default(),
+                body_contains: options.body_contains,
+                body_regex: options
+                    .body_regex
+                    .map(|x| Regex::new(&x))
+                    .transpose()
+                    .map_err(|err| format!("Invalid body regex: {}", err))?,
+                headers: options
+                    .header
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|x| x.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|err| format!("Invalid header rule: {}", err))?,
+            },
+            unique_exit: options.unique_exit.unwrap(),
+            retry: RetryPolicy {
+                retries: options.retries.unwrap(),
+                backoff: Duration::from_millis(options.backoff.unwrap()),
+            },
+        })
+    }
+}
+
+/// Options of a run as they are written in config files, named after the flags.
+///
+/// Unset options are taken from the underlying layer, see `merge`.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Options {
+    pub target: Option<String>,
+    pub input: Option<Vec<String>>,
+    pub input_format: Option<String>,
+    pub sources: Option<String>,
+    pub sources_proxy: Option<String>,
+    pub output: Option<String>,
+    pub output_format: Option<String>,
+    pub failed_output: Option<String>,
+    pub report: Option<String>,
+    pub state: Option<String>,
+    pub cores: Option<usize>,
+    pub cps: Option<f64>,
+    pub max_inflight: Option<usize>,
+    pub timeout: Option<u64>,
+    pub deadline: Option<u64>,
+    pub grace: Option<u64>,
+    pub retries: Option<u32>,
+    pub backoff: Option<u64>,
+    pub judge: Option<String>,
+    pub real_ip: Option<String>,
+    pub min_anonymity: Option<String>,
+    pub sort: Option<bool>,
+    pub unique_exit: Option<bool>,
+    pub status: Option<String>,
+    pub body_contains: Option<String>,
+    pub body_regex: Option<String>,
+    pub header: Option<Vec<String>>,
+}
+
+impl Options {
+    /// Options used unless they are set otherwise.
+    pub fn defaults() -> Self {
+        Options {
+            target: Some("https://ifconfig.me".into()),
+            input_format: Some("auto".into()),
+            output: Some("valid.txt".into()),
+            output_format: Some("txt".into()),
+            cores: Some(num_cpus::get()),
+            cps: Some(30.0),
+            timeout: Some(5),
+            grace: Some(5),
+            retries: Some(0),
+            backoff: Some(500),
+            sort: Some(false),
+            unique_exit: Some(false),
+            ..Options::default()
+        }
+    }
+
+    /// Loads options of a toml file, or a yaml one by the `.yaml` and `.yml` extensions.
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read config `{}`: {}", path, err))?;
+        let options = match Path::new(path).extension().and_then(|x| x.to_str()) {
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&text).map_err(|err| err.to_string())
+            }
+            _ => toml::from_str(&text).map_err(|err| err.to_string()),
+        };
+
+        options.map_err(|err| format!("Invalid config `{}`: {}", path, err).into())
+    }
+
+    /// Options given by flags.
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, Box<dyn Error>> {
+        let value = |name| matches.value_of(name).map(String::from);
+        let values = |name| {
+            matches
+                .values_of(name)
+                .map(|x| x.map(String::from).collect())
+        };
+        // `--no-*` flags turn off what the config turned on
+        let flag = |name, negated| match (matches.is_present(name), matches.is_present(negated)) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        };
+
+        Ok(Options {
+            target: value("target"),
+            input: values("input"),
+            input_format: value("input_format"),
+            sources: value("sources"),
+            sources_proxy: value("sources_proxy"),
+            output: value("output"),
+            output_format: value("output_format"),
+            failed_output: value("failed_output"),
+            report: value("report"),
+            state: value("state"),
+            cores: parse_flag(matches, "cores", "amount of cores")?,
+            cps: parse_flag(matches, "cons_per_sec", "amount of connections")?,
+            max_inflight: parse_flag(matches, "max_inflight", "max amount of running checks")?,
+            timeout: parse_flag(matches, "timeout", "timeout")?,
+            deadline: parse_flag(matches, "deadline", "deadline")?,
+            grace: parse_flag(matches, "grace", "grace period")?,
+            retries: parse_flag(matches, "retries", "amount of retries")?,
+            backoff: parse_flag(matches, "backoff", "backoff")?,
+            judge: value("judge"),
+            real_ip: value("real_ip"),
+            min_anonymity: value("min_anonymity"),
+            sort: flag("sort", "no_sort"),
+            unique_exit: flag("unique_exit", "no_unique_exit"),
+            status: value("status"),
+            body_contains: value("body_contains"),
+            body_regex: value("body_regex"),
+            header: values("header"),
+        })
+    }
+
+    /// Options given by `PROXY_FIND_*` variables named after the options,
+    /// e.g. `PROXY_FIND_MAX_INFLIGHT`, lists are comma separated.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let mut options = Options::default();
+        for (name, value) in env::vars() {
+            let key = match name.strip_prefix(ENV_PREFIX) {
+                Some(key) if name != CONFIG_VAR => key.to_lowercase().replace('_', "-"),
+                _ => continue,
+            };
+            let option = Options::from_var(key, &value)
+                .map_err(|err| format!("Invalid `{}`: {}", name, err))?;
+            options = options.merge(option);
+        }
+
+        Ok(options)
+    }
+
+    /// Reads a single option, trying the value as every type the option may have.
+    fn from_var(key: String, value: &str) -> Result<Self, toml::de::Error> {
+        let typed = if let Ok(x) = value.parse() {
+            Some(TomlValue::Boolean(x))
+        } else if let Ok(x) = value.parse() {
+            Some(TomlValue::Integer(x))
+        } else if let Ok(x) = value.parse() {
+            Some(TomlValue::Float(x))
+        } else {
+            None
+        };
+        let string = TomlValue::String(value.into());
+        let list = TomlValue::Array(
+            value
+                .split(',')
+                .map(|x| TomlValue::String(x.trim().into()))
+                .collect(),
+        );
+
+        let mut error = None;
+        for value in typed.into_iter().chain(vec![string, list]) {
+            let mut table = TomlTable::new();
+            table.insert(key.clone(), value);
+            match TomlValue::Table(table).try_into() {
+                Ok(options) => return Ok(options),
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+
+        Err(error.unwrap())
+    }
+
+    /// Overrides these options by every option set in `over`.
+    pub fn merge(self, over: Options) -> Self {
+        Options {
+            target: over.target.or(self.target),
+            input: over.input.or(self.input),
+            input_format: over.input_format.or(self.input_format),
+            sources: over.sources.or(self.sources),
+            sources_proxy: over.sources_proxy.or(self.sources_proxy),
+            output: over.output.or(self.output),
+            output_format: over.output_format.or(self.output_format),
+            failed_output: over.failed_output.or(self.failed_output),
+            report: over.report.or(self.report),
+            state: over.state.or(self.state),
+            cores: over.cores.or(self.cores),
+            cps: over.cps.or(self.cps),
+            max_inflight: over.max_inflight.or(self.max_inflight),
+            timeout: over.timeout.or(self.timeout),
+            deadline: over.deadline.or(self.deadline),
+            grace: over.grace.or(self.grace),
+            retries: over.retries.or(self.retries),
+            backoff: over.backoff.or(self.backoff),
+            judge: over.judge.or(self.judge),
+            real_ip: over.real_ip.or(self.real_ip),
+            min_anonymity: over.min_anonymity.or(self.min_anonymity),
+            sort: over.sort.or(self.sort),
+            unique_exit: over.unique_exit.or(self.unique_exit),
+            status: over.status.or(self.status),
+            body_contains: over.body_contains.or(self.body_contains),
+            body_regex: over.body_regex.or(self.body_regex),
+            header: over.header.or(self.header),
+        }
+    }
+
+    /// Renders the options as a toml config file.
+    #[inline]
+    pub fn dump(&self) -> Result<String, Box<dyn Error>> {
+        Ok(toml::to_string(self)?)
+    }
+}
+
+/// Parses an option value, naming the option on failure.
+#[inline]
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, Box<dyn Error>> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid {} `{}`", name, value).into())
+}
+
+/// Parses a flag value if the flag was given.
+#[inline]
+fn parse_flag<T: FromStr>(
+    matches: &ArgMatches,
+    name: &str,
+    what: &str,
+) -> Result<Option<T>, Box<dyn Error>> {
+    matches.value_of(name).map(|x| parse(what, x)).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(key: &str, value: &str) -> Options {
+        Options::from_var(key.into(), value).unwrap()
+    }
+
+    #[test]
+    fn guesses_variable_types() {
+        assert_eq!(var("sort", "true").sort, Some(true));
+        assert_eq!(var("max-inflight", "10").max_inflight, Some(10));
+        assert_eq!(var("cps", "2.5").cps, Some(2.5));
+        assert_eq!(var("cps", "3").cps, Some(3.0));
+        assert_eq!(
+            var("target", "http://a.com").target.as_deref(),
+            Some("http://a.com")
+        );
+        // values that look like numbers or booleans still fit string options
+        assert_eq!(var("status", "200").status.as_deref(), Some("200"));
+        assert_eq!(
+            var("body-contains", "true").body_contains.as_deref(),
+            Some("true")
+        );
+        assert_eq!(
+            var("real-ip", "1.2.3.4").real_ip.as_deref(),
+            Some("1.2.3.4")
+        );
+        // lists are split by commas, a single value is a list too
+        assert_eq!(
+            var("input", "a.txt, b.txt").input,
+            Some(vec!["a.txt".into(), "b.txt".into()])
+        );
+        assert_eq!(var("header", "Server").header, Some(vec!["Server".into()]));
+
+        assert!(Options::from_var("timeout".into(), "soon").is_err());
+        assert!(Options::from_var("sort".into(), "yes").is_err());
+        assert!(Options::from_var("unknown".into(), "1").is_err());
+    }
+
+    #[test]
+    fn overrides_file_by_env_by_flags() {
+        let file: Options = toml::from_str(
+            "target = \"http://file.com\"\ntimeout = 10\nretries = 3\ncps = 5.0\nsort = true",
+        )
+        .unwrap();
+        let env = var("timeout", "20").merge(var("retries", "4"));
+        let matches = app()
+            .get_matches_from_safe(vec!["proxy-find", "--timeout", "30", "--no-sort"])
+            .unwrap();
+        let flags = Options::from_matches(&matches).unwrap();
+
+        let options = Options::defaults().merge(file).merge(env).merge(flags);
+        assert_eq!(options.timeout, Some(30));
+        assert_eq!(options.sort, Some(false));
+        assert_eq!(options.retries, Some(4));
+        assert_eq!(options.cps, Some(5.0));
+        assert_eq!(options.target.as_deref(), Some("http://file.com"));
+        assert_eq!(options.grace, Some(5));
+        assert_eq!(options.deadline, None);
+    }
+}
@@ -0,0 +1,217 @@
+use std::time::Duration;
+use reqwest::Url;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, Error as IoError},
+    net::TcpStream,
+    time,
+};
+use crate::{limit::RateLimiter, socks4::Socks4};
+
+/// Proxy protocols that can be detected for a scheme-less `host:port`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Http,
+    Socks4,
+    Socks5,
+}
+
+impl Protocol {
+    /// Scheme used to record the proxy in the output.
+    pub fn scheme(self) -> &'static str {
+        match self {
+            Protocol::Http => "http",
+            Protocol::Socks4 => "socks4",
+            Protocol::Socks5 => "socks5",
+        }
+    }
+
+    /// Url of the proxy as it is written to the output.
+    pub fn url(self, addr: &str) -> String {
+        [self.scheme(), "://", addr].concat()
+    }
+}
+
+/// Tries every known handshake against `[user:pass@]host:port` and returns the protocols that answered.
+pub async fn detect(
+    addr: &str,
+    target: &Url,
+    timeout: u64,
+    limiter: Option<&RateLimiter>,
+) -> Vec<Protocol> {
+    let (auth, addr) = match addr.rsplit_once('@') {
+        Some((_, addr)) => (true, addr),
+        None => (false, addr),
+    };
+    let host = target.host_str().unwrap_or_default();
+    let port = target.port_or_known_default().unwrap_or(80);
+    let timeout = Duration::from_secs(timeout);
+
+    let (http, connect, socks4, socks5) = futures::join!(
+        probe(timeout, limiter, probe_http(addr, host)),
+        probe(timeout, limiter, probe_connect(addr, host, port)),
+        probe(timeout, limiter, probe_socks4(addr, host, port)),
+        probe(timeout, limiter, probe_socks5(addr, auth)),
+    );
+
+    // proxies tunneling by `CONNECT` are still plain http ones, `https://` would mean tls to the proxy
+    [
+        (Protocol::Http, http || connect),
+        (Protocol::Socks4, socks4),
+        (Protocol::Socks5, socks5),
+    ]
+    .iter()
+    .filter(|(_, answered)| *answered)
+    .map(|(protocol, _)| *protocol)
+    .collect()
+}
+
+#[inline]
+async fn probe<F>(timeout: Duration, limiter: Option<&RateLimiter>, handshake: F) -> bool
+where
+    F: std::future::Future<Output = Result<bool, IoError>>,
+{
+    // every probe is a connection of its own
+    if let Some(limiter) = limiter {
+        limiter.acquire().await;
+    }
+    matches!(time::timeout(timeout, handshake).await, Ok(Ok(true)))
+}
+
+/// Plain http proxy: absolute-form request must be answered with a http status line.
+async fn probe_http(addr: &str, host: &str) -> Result<bool, IoError> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let request = format!(
+        "GET http://{0}/ HTTP/1.1\r\nHost: {0}\r\nConnection: close\r\n\r\n",
+        host
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut buf = [0; 12];
+    stream.read_exact(&mut buf).await?;
+
+    Ok(buf.starts_with(b"HTTP/1."))
+}
+
+/// Http proxy tunneling: `CONNECT` tunnel to the target must be established.
+async fn probe_connect(addr: &str, host: &str, port: u16) -> Result<bool, IoError> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let request = format!(
+        "CONNECT {0}:{1} HTTP/1.1\r\nHost: {0}:{1}\r\n\r\n",
+        host, port
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut buf = [0; 12];
+    stream.read_exact(&mut buf).await?;
+
+    Ok(buf.starts_with(b"HTTP/1.") && &buf[9..12] == b"200")
+}
+
+/// Socks4 proxy: connect request to the target must be granted.
+async fn probe_socks4(addr: &str, host: &str, port: u16) -> Result<bool, IoError> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let proxy = Socks4 {
+        user_id: String::new(),
+        remote_dns: false,
+    };
+    proxy.handshake(&mut stream, host, port).await?;
+
+    Ok(true)
+}
+
+/// Socks5 proxy: greeting without authentication, or with password if it's known, must be accepted.
+async fn probe_socks5(addr: &str, auth: bool) -> Result<bool, IoError> {
+    let mut stream = TcpStream::connect(addr).await?;
+    if auth {
+        stream.write_all(&[0x05, 0x02, 0x00, 0x02]).await?;
+    } else {
+        stream.write_all(&[0x05, 0x01, 0x00]).await?;
+    }
+
+    let mut buf = [0; 2];
+    stream.read_exact(&mut buf).await?;
+
+    Ok(buf == [0x05, 0x00] || (auth && buf == [0x05, 0x02]))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use super::*;
+
+    /// Answer of a fake proxy to the first bytes of a connection, `None` closes it.
+    type Answer = fn(&[u8]) -> Option<&'static [u8]>;
+
+    /// Answers every connection until the test ends, returns the address.
+    async fn serve(answer: Answer) -> String {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    let read = stream.read(&mut buf).await.unwrap_or_default();
+                    if let Some(reply) = answer(&buf[..read]) {
+                        let _ = stream.write_all(reply).await;
+                    }
+                });
+            }
+        });
+
+        addr.to_string()
+    }
+
+    #[tokio::test]
+    async fn detects_protocols_by_handshakes() {
+        let http: Answer = |request| match request {
+            [b'G', b'E', b'T', b' ', ..] => Some(b"HTTP/1.1 200 OK\r\n\r\n"),
+            [b'C', b'O', b'N', ..] => Some(b"HTTP/1.1 405 Method Not Allowed\r\n\r\n"),
+            _ => None,
+        };
+        let connect: Answer = |request| match request {
+            [b'C', b'O', b'N', ..] => Some(b"HTTP/1.1 200 Connection established\r\n\r\n"),
+            _ => None,
+        };
+        let socks4: Answer = |request| match request {
+            [0x04, ..] => Some(&[0x00, 0x5A, 0, 0, 0, 0, 0, 0]),
+            _ => None,
+        };
+        let socks5: Answer = |request| match request {
+            [0x05, ..] => Some(&[0x05, 0x00]),
+            _ => None,
+        };
+        let garbage: Answer = |_| Some(b"garbage, nothing but garbage\r\n\r\n");
+        let cases: [(Answer, &[Protocol]); 5] = [
+            (http, &[Protocol::Http]),
+            (connect, &[Protocol::Http]),
+            (socks4, &[Protocol::Socks4]),
+            (socks5, &[Protocol::Socks5]),
+            (garbage, &[]),
+        ];
+
+        // socks4 resolves the target locally, it has to be an ip
+        let target = Url::parse("http://127.0.0.1/").unwrap();
+        for (i, (answer, protocols)) in cases.iter().enumerate() {
+            let addr = serve(*answer).await;
+            assert_eq!(detect(&addr, &target, 2, None).await, *protocols, "{}", i);
+        }
+    }
+
+    #[tokio::test]
+    async fn detects_socks5_asking_for_password() {
+        let socks5: Answer = |request| match request {
+            [0x05, ..] => Some(&[0x05, 0x02]),
+            _ => None,
+        };
+        let target = Url::parse("http://127.0.0.1/").unwrap();
+        let addr = serve(socks5).await;
+
+        assert_eq!(
+            detect(&format!("user:pass@{}", addr), &target, 2, None).await,
+            [Protocol::Socks5]
+        );
+        assert!(detect(&addr, &target, 2, None).await.is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::OnceLock;
+use regex::Regex;
+use crate::normalize;
+
+static PATTERN: OnceLock<Regex> = OnceLock::new();
+
+/// Finds `ip:port` candidates in arbitrary text or html.
+///
+/// Besides `ip:port` in text, ip and port may sit in adjacent table cells
+/// or be separated by any other markup, e.g. `<td>1.2.3.4</td><td>8080</td>`.
+pub fn extract(text: &str) -> Vec<String> {
+    let text = strip_markup(text);
+    let pattern = PATTERN.get_or_init(|| {
+        // `\t` is what tags are replaced with
+        Regex::new(
+            r"(?:^|[^\d.])(\d{1,3}(?:\.\d{1,3}){3})(?:\s*:\s*|[ \r\n]*\t[\t \r\n]*)(\d{1,5})(?:$|\D)",
+        )
+        .unwrap()
+    });
+
+    let mut proxies = Vec::new();
+    let mut start = 0;
+    while let Some(captures) = pattern.captures_at(&text, start) {
+        let (ip, port) = (&captures[1], &captures[2]);
+        // matches overlap by the delimiters around them
+        start = captures.get(2).unwrap().end();
+
+        // octets with leading zeros are left for the normalizer to canonicalize
+        let valid =
+            normalize::parse_ipv4(ip).is_some() && port.parse::<u16>().is_ok_and(|port| port > 0);
+        if valid {
+            proxies.push(format!("{}:{}", ip, port));
+        }
+    }
+
+    proxies
+}
+
+/// Replaces html tags with `\t` and decodes the most common entities.
+#[inline]
+fn strip_markup(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find('<') {
+        stripped.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        match rest.find('>') {
+            Some(end) => {
+                stripped.push('\t');
+                rest = &rest[end + 1..];
+            }
+            None => break,
+        }
+    }
+    stripped.push_str(rest);
+
+    stripped
+        .replace("&nbsp;", " ")
+        .replace("&#58;", ":")
+        .replace("&colon;", ":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_from_text() {
+        let text = "proxies: 1.2.3.4:80, 5.6.7.8 : 3128;9.9.9.9:8080\nversion 1.2.3.4.5:80";
+        assert_eq!(
+            extract(text),
+            vec!["1.2.3.4:80", "5.6.7.8:3128", "9.9.9.9:8080"]
+        );
+    }
+
+    #[test]
+    fn extracts_from_html() {
+        let html = "<table>\n<tr><td>1.2.3.4</td>\n  <td>8080</td><td>US</td></tr>\n\
+                    <tr><td>010.002.003.004</td><td>3128</td></tr>\n\
+                    <tr><td>5.6.7.8&#58;1080</td></tr>\n</table>";
+        assert_eq!(
+            extract(html),
+            vec!["1.2.3.4:8080", "010.002.003.004:3128", "5.6.7.8:1080"]
+        );
+    }
+
+    #[test]
+    fn skips_invalid_candidates() {
+        let text = "1.2.3.256:80 1.2.3.4:0 1.2.3.4:65536 1.2.3.4:123456 1.2.3.4 80";
+        assert!(extract(text).is_empty());
+    }
+}
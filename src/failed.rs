@@ -0,0 +1,45 @@
+use tokio::{
+    fs,
+    io::{AsyncWriteExt, Error as IoError},
+    sync::Mutex,
+};
+use proxy_find::outcome::Rejection;
+
+/// Output of failed proxies, `<proxy>\t<reason>\t<attempts>\t<message>` per line.
+pub struct FailedList {
+    file: Mutex<fs::File>,
+}
+
+impl FailedList {
+    pub async fn create(path: &str, append: bool) -> Result<Self, IoError> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .await?;
+
+        Ok(FailedList {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub async fn push(&self, proxy: &str, rejection: &Rejection) -> Result<(), IoError> {
+        // messages may span lines, the record must not
+        let message = rejection.message.replace(['\t', '\r', '\n'], " ");
+        let line = format!(
+            "{}\t{}\t{}\t{}\n",
+            proxy,
+            rejection.outcome.as_str(),
+            rejection.attempts,
+            message
+        );
+
+        self.file.lock().await.write_all(line.as_bytes()).await
+    }
+
+    pub async fn flush(&self) -> Result<(), IoError> {
+        self.file.lock().await.flush().await
+    }
+}
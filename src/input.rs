@@ -0,0 +1,254 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
+use serde_json::Value;
+use tokio::{
+    fs,
+    io::{self, AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader, Error as IoError},
+    stream::StreamExt,
+    sync::mpsc,
+};
+use proxy_find::{
+    extract,
+    normalize::{DropReason, Dropped, Normalizer},
+    parse::{self, CsvParser, InputFormat, ProxyRecord},
+    sources::Fetched,
+    ProxyEntry,
+};
+use crate::stats::Stats;
+
+/// Input to read proxies from.
+pub enum Source {
+    Stdin,
+    File(PathBuf),
+    /// Proxies already fetched from a list url.
+    Fetched(Fetched),
+}
+
+impl Source {
+    pub fn name(&self) -> Arc<str> {
+        match self {
+            Source::Stdin => "stdin".into(),
+            Source::File(path) => path.to_string_lossy().into(),
+            Source::Fetched(fetched) => Arc::clone(&fetched.url),
+        }
+    }
+}
+
+/// Expands input paths: `-` is stdin, directories are all the files inside them,
+/// paths with `*`, `?` or `[` are glob patterns.
+pub fn expand(inputs: &[Box<str>]) -> Result<Vec<Source>, Box<dyn Error>> {
+    let mut sources = Vec::new();
+    for input in inputs {
+        if input.as_ref() == "-" {
+            sources.push(Source::Stdin);
+            continue;
+        }
+
+        let paths = if input.contains(['*', '?', '[']) {
+            let paths = glob::glob(input)?.collect::<Result<Vec<_>, _>>()?;
+            if paths.is_empty() {
+                return Err(format!("No files match `{}`", input).into());
+            }
+            paths
+        } else {
+            vec![PathBuf::from(input.as_ref())]
+        };
+
+        for path in paths {
+            if path.is_dir() {
+                sources.extend(list_dir(&path)?.into_iter().map(Source::File));
+            } else {
+                sources.push(Source::File(path));
+            }
+        }
+    }
+
+    Ok(sources)
+}
+
+#[inline]
+fn list_dir(path: &Path) -> Result<Vec<PathBuf>, IoError> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Reads proxies of every source, normalizes them and sends into the channel.
+pub struct Reader {
+    format: InputFormat,
+    /// Proxies checked by previous runs, skipped.
+    checked: HashSet<String>,
+    dropped: Arc<Dropped>,
+    stats: Arc<Stats>,
+    proxies: mpsc::Sender<ProxyEntry>,
+    normalizer: Normalizer,
+}
+
+impl Reader {
+    pub fn new(
+        format: InputFormat,
+        checked: HashSet<String>,
+        dropped: Arc<Dropped>,
+        stats: Arc<Stats>,
+        proxies: mpsc::Sender<ProxyEntry>,
+    ) -> Self {
+        Reader {
+            format,
+            checked,
+            dropped,
+            stats,
+            proxies,
+            normalizer: Normalizer::default(),
+        }
+    }
+
+    pub async fn stream(mut self, sources: Vec<Source>) -> Result<(), IoError> {
+        let result = self.stream_sources(sources).await;
+        // even a failed read is all the input there is, an interrupted one is not
+        if result.as_ref().map_or(true, |read_all| *read_all) {
+            self.stats.read_all.store(true, Ordering::Relaxed);
+        }
+
+        result.map(|_| ())
+    }
+
+    /// Sends proxies of every source, returns `false` once the receiver is gone.
+    async fn stream_sources(&mut self, sources: Vec<Source>) -> Result<bool, IoError> {
+        for source in sources {
+            let name = source.name();
+            let (format, input): (_, Box<dyn AsyncRead + Unpin + Send>) = match source {
+                Source::Stdin => (self.format, Box::new(io::stdin())),
+                Source::File(path) => (
+                    self.format.resolve(Some(&path)),
+                    Box::new(fs::File::open(path).await?),
+                ),
+                Source::Fetched(fetched) => {
+                    for record in fetched.records {
+                        if !self.send(record, &name).await {
+                            return Ok(false);
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            if !self.read(format, input, &name).await? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Reads a single source, returns `false` once the receiver is gone.
+    async fn read(
+        &mut self,
+        format: InputFormat,
+        mut input: Box<dyn AsyncRead + Unpin + Send>,
+        source: &Arc<str>,
+    ) -> Result<bool, IoError> {
+        // json arrays can't be streamed line by line
+        if format == InputFormat::Json {
+            let mut json = Vec::new();
+            input.read_to_end(&mut json).await?;
+
+            let records = match serde_json::from_slice(&json) {
+                Ok(Value::Array(values)) => values.into_iter().map(parse::parse_json).collect(),
+                _ => vec![None],
+            };
+            for record in records {
+                if !self.send(record.ok_or(DropReason::Invalid), source).await {
+                    return Ok(false);
+                }
+            }
+
+            return Ok(true);
+        }
+        // candidates may span lines, e.g. table cells
+        if format == InputFormat::Extract {
+            let mut text = Vec::new();
+            input.read_to_end(&mut text).await?;
+
+            for proxy in extract::extract(&String::from_utf8_lossy(&text)) {
+                let record = parse::parse_plain(&proxy).ok_or(DropReason::Invalid);
+                if !self.send(record, source).await {
+                    return Ok(false);
+                }
+            }
+
+            return Ok(true);
+        }
+
+        let mut csv: Option<CsvParser> = None;
+        let mut lines = BufReader::new(input).lines();
+        while let Some(line) = lines.next().await {
+            let line = line?;
+            let line = line.trim();
+
+            let record = if line.is_empty() {
+                Err(DropReason::Blank)
+            } else if line.starts_with('#') {
+                Err(DropReason::Comment)
+            } else if format == InputFormat::Csv {
+                match &csv {
+                    Some(csv) => csv.parse(line).ok_or(DropReason::Invalid),
+                    None => {
+                        csv = Some(CsvParser::new(line));
+                        continue;
+                    }
+                }
+            } else {
+                parse::parse_line(format, line).ok_or(DropReason::Invalid)
+            };
+
+            if !self.send(record, source).await {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Sends a parsed proxy to be checked, returns `false` once the receiver is gone.
+    async fn send(&mut self, record: Result<ProxyRecord, DropReason>, source: &Arc<str>) -> bool {
+        let normalized = record.and_then(|record| {
+            let proxy = self.normalizer.push(&record.url())?;
+            Ok((proxy, record.extra))
+        });
+
+        let (proxy, extra) = match normalized {
+            Ok(normalized) => normalized,
+            Err(reason) => {
+                self.dropped.count(reason);
+                return true;
+            }
+        };
+        if self.checked.contains(&proxy) {
+            return true;
+        }
+
+        let entry = ProxyEntry {
+            proxy,
+            extra,
+            source: Arc::clone(source),
+        };
+        // receiver is gone once scheduling was interrupted
+        let sent = self.proxies.send(entry).await.is_ok();
+        if sent {
+            self.stats.read.fetch_add(1, Ordering::Relaxed);
+        }
+
+        sent
+    }
+}
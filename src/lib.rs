@@ -0,0 +1,21 @@
+//! Finding working proxies in proxy lists.
+//!
+//! `ProxyChecker` checks proxies against a target, the rest are the pieces
+//! it's fed with: parsing, normalizing and fetching proxy lists.
+
+pub mod anonymity;
+pub mod checker;
+pub mod client;
+mod detect;
+pub mod extract;
+mod limit;
+pub mod normalize;
+pub mod outcome;
+pub mod parse;
+pub mod retry;
+mod socks4;
+pub mod sources;
+pub mod validate;
+
+pub use checker::{CheckResult, ProxyChecker, ProxyCheckerBuilder, ProxyEntry};
+pub use outcome::CheckOutcome;
@@ -0,0 +1,74 @@
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+/// Token bucket limiting the rate of new connections.
+pub struct RateLimiter {
+    /// Tokens added per second.
+    rate: f64,
+    /// Max amount of tokens that can be saved up for a burst.
+    capacity: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a full bucket allowing `rate` connections per second, with bursts of up to a second.
+    pub fn new(rate: f64) -> Self {
+        let capacity = rate.max(1.0);
+
+        RateLimiter {
+            rate,
+            capacity,
+            bucket: Mutex::new(Bucket {
+                tokens: capacity,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a token is available and takes it.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.refill();
+            // the token is taken in advance, so concurrent callers queue up behind each other
+            bucket.tokens -= 1.0;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / self.rate)
+        };
+
+        time::delay_for(wait).await;
+    }
+
+    /// Waits until a token is available without taking it.
+    pub async fn ready(&self) {
+        let wait = {
+            let bucket = self.refill();
+            if bucket.tokens >= 1.0 {
+                return;
+            }
+            Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate)
+        };
+
+        time::delay_for(wait).await;
+    }
+
+    #[inline]
+    fn refill(&self) -> MutexGuard<'_, Bucket> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled).as_secs_f64() * self.rate;
+        bucket.tokens = (bucket.tokens + refill).min(self.capacity);
+        bucket.refilled = now;
+
+        bucket
+    }
+}
@@ -1,93 +1,69 @@
-use std::{error::Error, sync::Arc, thread::sleep as thread_sleep, time::Duration};
-use clap::{App as ClapApp, Arg as ClapArg};
-use futures::stream::TryStreamExt;
-use reqwest::{Error as ReqwestError, Proxy};
+use std::{
+    error::Error,
+    net::IpAddr,
+    process,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+use futures::{
+    future,
+    stream::{self, StreamExt},
+};
+use reqwest::Url;
 use tokio::{
-    fs,
-    io::{BufReader, Error as IoError},
-    prelude::*,
+    io::Error as IoError,
     runtime::Builder as RuntimeBuilder,
-    sync::Mutex,
+    signal,
+    sync::{mpsc, oneshot, Notify},
+    time,
+};
+use proxy_find::{
+    anonymity::{self, Judge},
+    client::ProxyClient,
+    normalize::Dropped,
+    sources, ProxyChecker,
 };
+use config::{AppConfig, Options};
+use failed::FailedList;
+use input::{Reader, Source};
+use output::{Outputs, ValidList};
+use report::Report;
+use state::{State, StateLog};
+use stats::Stats;
 
-struct AppConfig {
-    pub target: Box<str>,
-    pub input: Box<str>,
-    pub output: Box<str>,
-    pub cores: usize,
-    pub delay: u64,
-    pub timeout: u64,
+mod config;
+mod failed;
+mod input;
+mod output;
+mod progress;
+mod report;
+mod state;
+mod stats;
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn run() -> Result<(), Box<dyn Error>> {
     // build a config
-    let matches = ClapApp::new("proxy-finder")
-        .author("q-chan")
-        .about("Simple utility for checking status of proxies by list")
-        .arg(
-            ClapArg::with_name("target")
-                .long("target")
-                .short("h")
-                .help("Target ip or website to test if proxy can connect other ips")
-                .default_value("https://ifconfig.me")
-                .takes_value(true),
-        )
-        .arg(
-            ClapArg::with_name("output")
-                .long("output")
-                .short("o")
-                .help("Output path of file where valid proxies will be saved")
-                .default_value("valid.txt")
-                .takes_value(true),
-        )
-        .arg(
-            ClapArg::with_name("input")
-                .long("input")
-                .short("i")
-                .help("Input path of file where proxies to check are located")
-                .required(true)
-                .takes_value(true),
-        )
-        .arg(
-            ClapArg::with_name("cons_per_sec")
-                .long("cps")
-                .help("Amount of connections per seconds")
-                .takes_value(true),
-        )
-        .arg(
-            ClapArg::with_name("cores")
-                .long("cores")
-                .short("c")
-                .help("Amount of cores used")
-                .takes_value(true),
-        )
-        .arg(
-            ClapArg::with_name("timeout")
-                .long("timeout")
-                .short("m")
-                .help("Max timeout(secs) of request")
-                .takes_value(true),
-        )
-        .get_matches();
-
-    let cfg = Arc::new(AppConfig {
-        target: matches.value_of("target").unwrap().into(),
-        input: matches.value_of("input").unwrap().into(),
-        output: matches.value_of("output").unwrap().into(),
-        delay: 1000
-            / matches
-                .value_of("cons_per_sec")
-                .map(|x| x.parse().expect("Invalid amount of connections"))
-                .unwrap_or(30),
-        cores: matches
-            .value_of("cores")
-            .map(|x| x.parse().expect("Invalid amount of cores"))
-            .unwrap_or_else(num_cpus::get),
-        timeout: matches
-            .value_of("timeout")
-            .map(|x| x.parse().expect("Invalid timeout"))
-            .unwrap_or(5),
-    });
+    let matches = config::app().get_matches();
+
+    // options of the config file are overridden by variables, and those by flags
+    let mut options = Options::default();
+    if let Some(path) = matches.value_of("config") {
+        options = Options::load(path)?;
+    }
+    let options = options
+        .merge(Options::from_env()?)
+        .merge(Options::from_matches(&matches)?);
+    if let ("config", Some(_)) = matches.subcommand() {
+        print!("{}", Options::defaults().merge(options).dump()?);
+        return Ok(());
+    }
+    let cfg = AppConfig::new(options)?;
 
     // build an runtime
     let mut runtime = RuntimeBuilder::new()
@@ -98,86 +74,253 @@ fn main() -> Result<(), Box<dyn Error>> {
         .enable_all()
         .build()?;
 
-    // read proxies
-    let proxies = runtime.block_on(load_list(&cfg.input))?;
+    // find out real ip to classify anonymity with
+    let judge = match &cfg.judge {
+        Some(url) => {
+            let real_ip = match cfg.real_ip {
+                Some(ip) => ip,
+                None => runtime
+                    .block_on(fetch_real_ip(url, cfg.timeout))?
+                    .ok_or("Judge did not answer with the real ip")?,
+            };
+            println!("Real ip is `{}`", real_ip);
 
-    // create valid file
-    let valid_file = Arc::new(Mutex::new(
-        runtime.block_on(fs::File::create(cfg.output.to_string()))?,
-    ));
+            Some(Judge {
+                url: url.clone(),
+                real_ip,
+            })
+        }
+        None => None,
+    };
+
+    // skip proxies checked by previous runs
+    let (state, state_log) = match &cfg.state {
+        Some(path) => (
+            runtime.block_on(state::load(path))?,
+            Some(runtime.block_on(StateLog::open(path))?),
+        ),
+        None => (State::default(), None),
+    };
+    if !state.checked.is_empty() {
+        println!(
+            "Resuming, `{}` proxies were already checked",
+            state.checked.len()
+        );
+    }
+
+    // previous results are kept when resuming
+    let valid_list = runtime.block_on(ValidList::open(
+        &cfg.output,
+        cfg.output_format,
+        cfg.sort,
+        state_log.as_ref().map(|_| &state),
+    ))?;
 
-    let len = proxies.len();
-    for (idx, proxy) in proxies.into_iter().enumerate() {
-        println!("{}%", (idx as f32 / len as f32 * 100.0) as u32);
-        runtime.spawn(process_proxy(
-            Arc::clone(&cfg),
-            Arc::clone(&valid_file),
-            proxy,
-        ));
+    let failed_list = match &cfg.failed_output {
+        Some(path) => Some(runtime.block_on(FailedList::create(path, state_log.is_some()))?),
+        None => None,
+    };
 
-        thread_sleep(Duration::from_millis(cfg.delay));
+    let mut checker = ProxyChecker::builder(cfg.target.clone())
+        .timeout(cfg.timeout)
+        .rules(cfg.rules.clone())
+        .retry(cfg.retry.clone())
+        .rate(cfg.cons_per_sec);
+    if let Some(judge) = judge {
+        checker = checker.judge(judge);
+    }
+    if let Some(anonymity) = cfg.min_anonymity {
+        checker = checker.min_anonymity(anonymity);
     }
+    if let Some(max) = cfg.max_inflight {
+        checker = checker.max_inflight(max);
+    }
+    if cfg.unique_exit {
+        // exit ips of proxies saved by previous runs are taken already
+        checker = checker.unique_exit(state.saved.iter().filter_map(|saved| saved.exit_ip));
+    }
+    let checker = checker.build();
 
-    println!(
-        "Waiting `{}` seconds to process all requests...",
-        cfg.timeout
+    // read proxies, streamed through a bounded channel to keep memory flat on huge lists
+    let mut sources = input::expand(&cfg.input)?;
+    if let Some(path) = &cfg.sources {
+        let list_sources = sources::load(path)?;
+        let client = ProxyClient::new(cfg.timeout, cfg.sources_proxy.as_deref())
+            .map_err(|err| err as Box<dyn Error>)?;
+        println!("Fetching `{}` proxy lists...", list_sources.len());
+        for fetched in runtime.block_on(sources::fetch_all(list_sources, client)) {
+            // failed lists are skipped
+            match fetched {
+                Ok(fetched) => sources.push(Source::Fetched(fetched)),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+    }
+    let started = Instant::now();
+    let (proxies_tx, proxies) = mpsc::channel(1024);
+    let dropped = Arc::new(Dropped::default());
+    let stats = Arc::new(Stats::default());
+    let reader = Reader::new(
+        cfg.input_format,
+        state.checked,
+        Arc::clone(&dropped),
+        Arc::clone(&stats),
+        proxies_tx,
     );
-    thread_sleep(Duration::from_secs(cfg.timeout));
+    let reader = runtime.spawn(reader.stream(sources));
 
-    Ok(())
-}
+    let progress_stop = Arc::new(Notify::new());
+    let progress = runtime.spawn(progress::show(
+        Arc::clone(&stats),
+        Arc::clone(&progress_stop),
+    ));
 
-// should be rewritten (using custom reqwest fork...)
-#[inline]
-async fn check_proxy(target: &str, timeout: u64, proxy: &str) -> Result<bool, ReqwestError> {
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(timeout))
-        .proxy(Proxy::all(proxy)?)
-        .build()?;
+    // interruption stops scheduling new checks, running ones get a grace period
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    // deadline starts once every proxy was scheduled
+    let (scheduled_tx, scheduled_rx) = oneshot::channel::<()>();
+    let scheduled_all = stream::once(async move {
+        let _ = scheduled_tx.send(());
+    });
+    let proxies = proxies
+        .take_until(stop_rx)
+        .chain(scheduled_all.filter_map(|()| future::ready(None)))
+        .inspect(|_| {
+            stats.scheduled.fetch_add(1, Ordering::Relaxed);
+        });
+    let mut results = Box::pin(checker.check_stream(proxies));
+    let outputs = Outputs {
+        cfg: &cfg,
+        valid_list: &valid_list,
+        failed_list: failed_list.as_ref(),
+        state_log: state_log.as_ref(),
+        stats: &stats,
+    };
 
-    let resp = client.get(target).send().await;
+    let interrupted = runtime.block_on(async {
+        let deadline = async {
+            let _ = scheduled_rx.await;
+            match cfg.deadline {
+                Some(secs) => time::delay_for(Duration::from_secs(secs)).await,
+                None => future::pending().await,
+            }
+        };
+        let shutdown = shutdown_signal();
+        tokio::pin!(deadline, shutdown);
 
-    Ok(resp.is_ok())
-}
+        loop {
+            let result = tokio::select! {
+                result = results.next() => result,
+                _ = &mut deadline => return Ok(false),
+                result = &mut shutdown => return result.map(|()| true),
+            };
+            match result {
+                Some(result) => outputs.save(result).await,
+                None => return Ok::<_, IoError>(false),
+            }
+        }
+    })?;
 
-enum ProcessProxyError {
-    Reqwest(ReqwestError),
-    Io(IoError),
-}
+    // progress line is finished before any other output
+    progress_stop.notify();
+    runtime.block_on(progress)?;
+
+    let running = |stats: &Stats| {
+        stats.scheduled.load(Ordering::Relaxed) - stats.checked.load(Ordering::Relaxed)
+    };
+    if interrupted {
+        let _ = stop_tx.send(());
+        println!(
+            "Interrupted, waiting up to `{}` seconds for `{}` running checks...",
+            cfg.grace,
+            running(&stats)
+        );
+        // another interruption stops waiting at once
+        runtime.block_on(async {
+            let wait_all = async {
+                while let Some(result) = results.next().await {
+                    outputs.save(result).await;
+                }
+            };
+            tokio::select! {
+                _ = time::timeout(Duration::from_secs(cfg.grace), wait_all) => Ok(()),
+                result = shutdown_signal() => result,
+            }
+        })?;
+    } else {
+        match runtime.block_on(reader) {
+            Ok(Err(err)) => eprintln!("Failed to read proxies: {}", err),
+            Err(err) => eprintln!("Failed to read proxies: {}", err),
+            Ok(Ok(())) => {}
+        }
 
-impl From<ReqwestError> for ProcessProxyError {
-    fn from(err: ReqwestError) -> Self {
-        ProcessProxyError::Reqwest(err)
+        if running(&stats) > 0 {
+            println!(
+                "Deadline passed, `{}` checks were still running",
+                running(&stats)
+            );
+        }
     }
-}
 
-impl From<IoError> for ProcessProxyError {
-    fn from(err: IoError) -> Self {
-        ProcessProxyError::Io(err)
+    runtime.block_on(valid_list.finish(&cfg.output))?;
+    if let Some(failed_list) = &failed_list {
+        runtime.block_on(failed_list.flush())?;
+    }
+    if let Some(state_log) = &state_log {
+        runtime.block_on(state_log.flush())?;
     }
-}
 
-#[inline]
-async fn process_proxy(
-    cfg: Arc<AppConfig>,
-    valid_file: Arc<Mutex<fs::File>>,
-    proxy: String,
-) -> Result<(), ProcessProxyError> {
-    if check_proxy(cfg.target.as_ref(), cfg.timeout, &proxy).await? {
-        valid_file
-            .lock()
-            .await
-            .write_all([&proxy, "\n"].concat().as_bytes())
-            .await?;
+    // proxies read but never checked, whether they were scheduled or not
+    let checked = stats.checked.load(Ordering::Relaxed);
+    println!(
+        "Checked `{}`, valid `{}`, remaining `{}`",
+        checked,
+        valid_list.saved.load(Ordering::Relaxed),
+        stats.read.load(Ordering::Relaxed) - checked
+    );
+    if !stats.read_all.load(Ordering::Relaxed) {
+        println!("Input was not read to the end, unread proxies aren't counted as remaining");
     }
 
+    let report = Report::new(&stats, &dropped, started.elapsed());
+    report.print();
+    if let Some(path) = &cfg.report {
+        runtime.block_on(report.write(path))?;
+    }
+    if interrupted {
+        println!("Input was not checked to the end, run again with `--state` to resume");
+    }
+
+    // a read of stdin still open by the writer never finishes, it isn't waited for
+    runtime.shutdown_timeout(Duration::from_millis(100));
+
     Ok(())
 }
 
+/// Resolves once the process is asked to stop by SIGINT or SIGTERM, fails if they can't be listened to.
+async fn shutdown_signal() -> Result<(), IoError> {
+    #[cfg(unix)]
+    {
+        use signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        signal::ctrl_c().await
+    }
+}
+
 #[inline]
-async fn load_list(path: &str) -> Result<Vec<String>, Box<dyn Error>> {
-    Ok(BufReader::new(fs::File::open(path).await?)
-        .lines()
-        .try_collect::<Vec<_>>()
-        .await?)
+async fn fetch_real_ip(judge: &Url, timeout: u64) -> Result<Option<IpAddr>, Box<dyn Error>> {
+    let client = ProxyClient::new(timeout, None).map_err(|err| err as Box<dyn Error>)?;
+
+    anonymity::fetch_real_ip(judge, &client)
+        .await
+        .map_err(|err| err as Box<dyn Error>)
 }
@@ -0,0 +1,415 @@
+use std::{error::Error, io::ErrorKind as IoErrorKind};
+use reqwest::StatusCode;
+
+/// Outcome of checking a proxy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckOutcome {
+    Valid,
+    /// Proxy or target host couldn't be resolved.
+    DnsFailure,
+    ConnectionRefused,
+    /// Connection was reset or closed in the middle of the exchange.
+    ConnectionClosed,
+    ConnectTimeout,
+    ReadTimeout,
+    TlsError,
+    /// Proxy answered `407` or rejected the credentials.
+    ProxyAuthRequired,
+    /// Proxy answered `502`, failing to reach the target itself.
+    BadGateway,
+    StatusMismatch,
+    HeaderMismatch,
+    BodyMismatch,
+    /// Proxy doesn't speak the protocol it was checked with.
+    ProtocolMismatch,
+    /// None of the protocols answered the handshake.
+    NoProtocol,
+    InvalidProxy,
+    /// Proxy is less anonymous than required.
+    Anonymity,
+    /// Another proxy with the same exit ip was already saved.
+    DuplicateExit,
+    /// Failure which doesn't fit any other outcome.
+    Other,
+}
+
+impl CheckOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CheckOutcome::Valid => "valid",
+            CheckOutcome::DnsFailure => "dns-failure",
+            CheckOutcome::ConnectionRefused => "connection-refused",
+            CheckOutcome::ConnectionClosed => "connection-closed",
+            CheckOutcome::ConnectTimeout => "connect-timeout",
+            CheckOutcome::ReadTimeout => "read-timeout",
+            CheckOutcome::TlsError => "tls-error",
+            CheckOutcome::ProxyAuthRequired => "proxy-auth-required",
+            CheckOutcome::BadGateway => "bad-gateway",
+            CheckOutcome::StatusMismatch => "status-mismatch",
+            CheckOutcome::HeaderMismatch => "header-mismatch",
+            CheckOutcome::BodyMismatch => "body-mismatch",
+            CheckOutcome::ProtocolMismatch => "protocol-mismatch",
+            CheckOutcome::NoProtocol => "no-protocol",
+            CheckOutcome::InvalidProxy => "invalid-proxy",
+            CheckOutcome::Anonymity => "anonymity",
+            CheckOutcome::DuplicateExit => "duplicate-exit",
+            CheckOutcome::Other => "other",
+        }
+    }
+
+    /// Whether a retry may pass, like after timeouts or connection resets.
+    pub fn is_transient(self) -> bool {
+        matches!(
+            self,
+            CheckOutcome::ConnectionClosed
+                | CheckOutcome::ConnectTimeout
+                | CheckOutcome::ReadTimeout
+                | CheckOutcome::BadGateway
+        )
+    }
+
+    /// Outcome of a response status the proxy itself answers with.
+    pub fn of_proxy_status(status: StatusCode) -> Option<Self> {
+        match status {
+            StatusCode::PROXY_AUTHENTICATION_REQUIRED => Some(CheckOutcome::ProxyAuthRequired),
+            StatusCode::BAD_GATEWAY => Some(CheckOutcome::BadGateway),
+            _ => None,
+        }
+    }
+
+    /// Classifies an error of connecting the proxy.
+    pub fn of_connect_error(err: &(dyn Error + 'static)) -> Self {
+        classify(err, CheckOutcome::ConnectTimeout)
+    }
+
+    /// Classifies an error of requesting the target through the proxy.
+    pub fn of_request_error(err: &(dyn Error + 'static)) -> Self {
+        classify(err, CheckOutcome::ReadTimeout)
+    }
+}
+
+/// Walks the source chain of the error for the first known cause.
+fn classify(err: &(dyn Error + 'static), timeout: CheckOutcome) -> CheckOutcome {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+            if err.is_timeout() {
+                return timeout;
+            }
+        }
+        if let Some(err) = err.downcast_ref::<hyper::Error>() {
+            if err.is_parse() {
+                return CheckOutcome::ProtocolMismatch;
+            }
+        }
+        if err.is::<native_tls::Error>() {
+            return CheckOutcome::TlsError;
+        }
+
+        if let Some(err) = err.downcast_ref::<std::io::Error>() {
+            // io errors often wrap the real cause
+            if let Some(outcome) = err
+                .get_ref()
+                .and_then(|inner| classify_inner(inner, timeout))
+            {
+                return outcome;
+            }
+
+            return match err.kind() {
+                IoErrorKind::TimedOut => timeout,
+                IoErrorKind::ConnectionRefused => CheckOutcome::ConnectionRefused,
+                IoErrorKind::ConnectionReset
+                | IoErrorKind::ConnectionAborted
+                | IoErrorKind::BrokenPipe
+                | IoErrorKind::UnexpectedEof => CheckOutcome::ConnectionClosed,
+                IoErrorKind::PermissionDenied => CheckOutcome::ProxyAuthRequired,
+                IoErrorKind::InvalidData => CheckOutcome::ProtocolMismatch,
+                _ => classify_message(&err.to_string()).unwrap_or(CheckOutcome::Other),
+            };
+        }
+
+        source = err.source();
+        // only leaves are plain messages, wrappers may have urls and such in them
+        if source.is_none() {
+            return classify_message(&err.to_string()).unwrap_or(CheckOutcome::Other);
+        }
+    }
+
+    CheckOutcome::Other
+}
+
+#[inline]
+fn classify_inner(
+    err: &(dyn Error + Send + Sync + 'static),
+    timeout: CheckOutcome,
+) -> Option<CheckOutcome> {
+    match classify(err, timeout) {
+        CheckOutcome::Other => None,
+        outcome => Some(outcome),
+    }
+}
+
+/// Falls back to messages for errors passed around as strings, like socks ones of `reqwest`.
+#[inline]
+fn classify_message(message: &str) -> Option<CheckOutcome> {
+    let message = message.to_ascii_lowercase();
+    let outcome = if message.contains("dns error") || message.contains("failed to lookup address") {
+        CheckOutcome::DnsFailure
+    } else if message.contains("refused") {
+        CheckOutcome::ConnectionRefused
+    } else if message.contains("timed out") {
+        CheckOutcome::ReadTimeout
+    } else if message.contains("authentication") || message.contains("password") {
+        CheckOutcome::ProxyAuthRequired
+    } else if message.contains("certificate") || message.contains("tls") || message.contains("ssl")
+    {
+        CheckOutcome::TlsError
+    } else if message.contains("socks") || message.contains("version") {
+        CheckOutcome::ProtocolMismatch
+    } else {
+        return None;
+    };
+
+    Some(outcome)
+}
+
+/// Why a check of a proxy failed.
+#[derive(Debug)]
+pub struct Rejection {
+    pub outcome: CheckOutcome,
+    pub message: String,
+    /// Amount of attempts used, `0` if the target was never requested.
+    pub attempts: u32,
+}
+
+impl Rejection {
+    pub fn new(outcome: CheckOutcome, message: impl Into<String>) -> Self {
+        Rejection {
+            outcome,
+            message: message.into(),
+            attempts: 0,
+        }
+    }
+
+    /// Rejection by an error, its whole source chain is kept as the message.
+    pub fn error(outcome: CheckOutcome, err: &(dyn Error + 'static)) -> Self {
+        let mut message = err.to_string();
+        let mut source = err.source();
+        while let Some(err) = source {
+            // some errors already include their sources
+            let cause = err.to_string();
+            if !message.contains(&cause) {
+                message.push_str(": ");
+                message.push_str(&cause);
+            }
+            source = err.source();
+        }
+
+        Rejection {
+            outcome,
+            message,
+            attempts: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fmt, time::Duration};
+    use hyper::{client::conn, Body, Request};
+    use tokio::{
+        io::AsyncWriteExt,
+        net::{TcpListener, TcpStream},
+    };
+    use super::*;
+
+    /// Error passed around as a plain message, like socks ones of `reqwest`.
+    #[derive(Debug)]
+    struct Message(&'static str);
+
+    impl fmt::Display for Message {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl Error for Message {}
+
+    /// Answers every connection with `reply`, or keeps it open without a word, returns the address.
+    async fn serve(reply: Option<&'static [u8]>) -> String {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut silent = Vec::new();
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                match reply {
+                    Some(reply) => {
+                        let _ = stream.write_all(reply).await;
+                    }
+                    None => silent.push(stream),
+                }
+            }
+        });
+
+        addr.to_string()
+    }
+
+    #[test]
+    fn classifies_io_errors() {
+        let kinds = [
+            (
+                IoErrorKind::ConnectionRefused,
+                CheckOutcome::ConnectionRefused,
+            ),
+            (IoErrorKind::ConnectionReset, CheckOutcome::ConnectionClosed),
+            (IoErrorKind::BrokenPipe, CheckOutcome::ConnectionClosed),
+            (IoErrorKind::UnexpectedEof, CheckOutcome::ConnectionClosed),
+            (
+                IoErrorKind::PermissionDenied,
+                CheckOutcome::ProxyAuthRequired,
+            ),
+            (IoErrorKind::InvalidData, CheckOutcome::ProtocolMismatch),
+            (IoErrorKind::Other, CheckOutcome::Other),
+        ];
+        for (kind, outcome) in kinds.iter() {
+            let err = std::io::Error::from(*kind);
+            assert_eq!(CheckOutcome::of_request_error(&err), *outcome, "{:?}", kind);
+        }
+
+        let timeout = std::io::Error::from(IoErrorKind::TimedOut);
+        assert_eq!(
+            CheckOutcome::of_connect_error(&timeout),
+            CheckOutcome::ConnectTimeout
+        );
+        assert_eq!(
+            CheckOutcome::of_request_error(&timeout),
+            CheckOutcome::ReadTimeout
+        );
+
+        // wrapped causes go before the kind
+        let wrapped = std::io::Error::other(Message("authentication failed"));
+        assert_eq!(
+            CheckOutcome::of_request_error(&wrapped),
+            CheckOutcome::ProxyAuthRequired
+        );
+        let wrapped = std::io::Error::other("failed to lookup address");
+        assert_eq!(
+            CheckOutcome::of_request_error(&wrapped),
+            CheckOutcome::DnsFailure
+        );
+    }
+
+    #[test]
+    fn classifies_string_errors() {
+        let messages = [
+            ("dns error: no such host", Some(CheckOutcome::DnsFailure)),
+            ("Connection refused", Some(CheckOutcome::ConnectionRefused)),
+            ("operation timed out", Some(CheckOutcome::ReadTimeout)),
+            (
+                "Password authentication failed",
+                Some(CheckOutcome::ProxyAuthRequired),
+            ),
+            ("invalid certificate", Some(CheckOutcome::TlsError)),
+            (
+                "unsupported SOCKS version",
+                Some(CheckOutcome::ProtocolMismatch),
+            ),
+            ("something went wrong", None),
+        ];
+        for (message, outcome) in messages.iter() {
+            assert_eq!(classify_message(message), *outcome, "{}", message);
+        }
+
+        assert_eq!(
+            CheckOutcome::of_request_error(&Message("socks connect error: connection refused")),
+            CheckOutcome::ConnectionRefused
+        );
+        assert_eq!(
+            CheckOutcome::of_request_error(&Message("something went wrong")),
+            CheckOutcome::Other
+        );
+    }
+
+    #[tokio::test]
+    async fn classifies_reqwest_and_hyper_errors() {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+
+        let silent = serve(None).await;
+        let err = client
+            .get(&format!("http://{}/", silent))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            CheckOutcome::of_request_error(&err),
+            CheckOutcome::ReadTimeout
+        );
+        assert_eq!(
+            CheckOutcome::of_connect_error(&err),
+            CheckOutcome::ConnectTimeout
+        );
+
+        let garbage = serve(Some(b"garbage\r\n\r\n")).await;
+        let err = client
+            .get(&format!("http://{}/", garbage))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            CheckOutcome::of_request_error(&err),
+            CheckOutcome::ProtocolMismatch
+        );
+
+        let stream = TcpStream::connect(&garbage).await.unwrap();
+        let (mut sender, connection) = conn::handshake(stream).await.unwrap();
+        tokio::spawn(connection);
+        let request = Request::get("/").body(Body::empty()).unwrap();
+        let err = sender.send_request(request).await.unwrap_err();
+        assert_eq!(
+            CheckOutcome::of_request_error(&err),
+            CheckOutcome::ProtocolMismatch
+        );
+
+        let closed = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap()
+        };
+        let err = client
+            .get(&format!("http://{}/", closed))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            CheckOutcome::of_request_error(&err),
+            CheckOutcome::ConnectionRefused
+        );
+    }
+
+    #[test]
+    fn retries_only_transient_outcomes() {
+        let transient = [
+            CheckOutcome::ConnectionClosed,
+            CheckOutcome::ConnectTimeout,
+            CheckOutcome::ReadTimeout,
+            CheckOutcome::BadGateway,
+        ];
+        let permanent = [
+            CheckOutcome::Valid,
+            CheckOutcome::DnsFailure,
+            CheckOutcome::ConnectionRefused,
+            CheckOutcome::TlsError,
+            CheckOutcome::ProxyAuthRequired,
+            CheckOutcome::StatusMismatch,
+            CheckOutcome::ProtocolMismatch,
+            CheckOutcome::Other,
+        ];
+
+        assert!(transient.iter().all(|outcome| outcome.is_transient()));
+        assert!(!permanent.iter().any(|outcome| outcome.is_transient()));
+    }
+}
@@ -0,0 +1,451 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, UNIX_EPOCH},
+};
+use reqwest::Url;
+use serde_json::json;
+use tokio::{
+    fs,
+    io::{AsyncWriteExt, Error as IoError},
+    sync::Mutex,
+};
+use proxy_find::{anonymity::Anonymity, checker::ProtocolCheck, CheckOutcome, CheckResult};
+use crate::{
+    config::AppConfig,
+    failed::FailedList,
+    state::{Saved, State, StateLog},
+    stats::Stats,
+};
+
+/// Format of the valid proxies output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Proxy per line followed by its anonymity, if it's known.
+    Txt,
+    /// Json array of objects.
+    Json,
+    /// Json object per line.
+    Jsonl,
+    /// Csv with a header row.
+    Csv,
+}
+
+impl OutputFormat {
+    /// First line of the output.
+    pub fn header(self) -> Option<&'static str> {
+        match self {
+            OutputFormat::Csv => Some(
+                "proxy,scheme,host,port,anonymity,exit_ip,connect_ms,total_ms,attempts,checked_at,target,source,extra",
+            ),
+            _ => None,
+        }
+    }
+
+    /// Whether the whole output has to be rewritten once all proxies are known.
+    pub fn is_document(self) -> bool {
+        self == OutputFormat::Json
+    }
+
+    /// Joins rendered proxies into the whole output.
+    pub fn document<'a, I>(self, lines: I) -> String
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let lines = lines.into_iter();
+        match self {
+            OutputFormat::Json => {
+                let items = lines.map(|line| ["  ", line].concat()).collect::<Vec<_>>();
+                if items.is_empty() {
+                    "[]\n".into()
+                } else {
+                    format!("[\n{}\n]\n", items.join(",\n"))
+                }
+            }
+            _ => {
+                let mut document = self
+                    .header()
+                    .map(|header| [header, "\n"].concat())
+                    .unwrap_or_default();
+                for line in lines {
+                    document.push_str(line);
+                    document.push('\n');
+                }
+                document
+            }
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "txt" => Ok(OutputFormat::Txt),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Valid proxy with everything the check found out about it.
+pub struct ValidProxy<'a> {
+    pub proxy: &'a str,
+    pub anonymity: Option<Anonymity>,
+    pub exit_ip: Option<IpAddr>,
+    pub connect_time: Duration,
+    pub total_time: Duration,
+    pub attempts: u32,
+    /// Unix timestamp(secs) of the check.
+    pub checked_at: u64,
+    pub target: &'a Url,
+    pub source: &'a str,
+    pub extra: &'a BTreeMap<String, String>,
+}
+
+impl ValidProxy<'_> {
+    /// Renders the proxy as a single line of the output format.
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Txt => self.to_txt(),
+            OutputFormat::Json | OutputFormat::Jsonl => self.to_json(),
+            OutputFormat::Csv => self.to_csv(),
+        }
+    }
+
+    fn to_txt(&self) -> String {
+        let mut line = self.proxy.to_owned();
+        if let Some(anonymity) = self.anonymity {
+            line.push(' ');
+            line.push_str(anonymity.as_str());
+        }
+
+        line
+    }
+
+    fn to_json(&self) -> String {
+        let url = Url::parse(self.proxy).ok();
+        let value = json!({
+            "proxy": self.proxy,
+            "scheme": url.as_ref().map(Url::scheme),
+            "host": url.as_ref().and_then(Url::host_str),
+            "port": url.as_ref().and_then(Url::port_or_known_default),
+            "anonymity": self.anonymity.map(Anonymity::as_str),
+            "exit_ip": self.exit_ip,
+            "connect_ms": self.connect_time.as_millis() as u64,
+            "total_ms": self.total_time.as_millis() as u64,
+            "attempts": self.attempts,
+            "checked_at": self.checked_at,
+            "target": self.target.as_str(),
+            "source": self.source,
+            "extra": self.extra,
+        });
+
+        value.to_string()
+    }
+
+    fn to_csv(&self) -> String {
+        let url = Url::parse(self.proxy).ok();
+        let extra = self
+            .extra
+            .iter()
+            // line breaks would split the record, every proxy is a single line
+            .map(|(key, value)| format!("{}={}", key, value).replace(['\r', '\n'], " "))
+            .collect::<Vec<_>>();
+        let row = [
+            self.proxy.to_owned(),
+            url.as_ref().map(Url::scheme).unwrap_or_default().into(),
+            url.as_ref()
+                .and_then(Url::host_str)
+                .unwrap_or_default()
+                .into(),
+            url.as_ref()
+                .and_then(Url::port_or_known_default)
+                .map(|port| port.to_string())
+                .unwrap_or_default(),
+            self.anonymity
+                .map(Anonymity::as_str)
+                .unwrap_or_default()
+                .into(),
+            self.exit_ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            self.connect_time.as_millis().to_string(),
+            self.total_time.as_millis().to_string(),
+            self.attempts.to_string(),
+            self.checked_at.to_string(),
+            self.target.to_string(),
+            self.source.to_owned(),
+            extra.join(";"),
+        ];
+
+        let mut writer = csv::WriterBuilder::new()
+            .terminator(csv::Terminator::Any(b'\n'))
+            .from_writer(Vec::new());
+        // writing into memory can't fail
+        writer.write_record(&row).unwrap();
+        let mut line = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        line.pop();
+
+        line
+    }
+}
+
+/// Output file of valid proxies.
+pub struct ValidList {
+    file: Mutex<fs::File>,
+    format: OutputFormat,
+    // lines are kept with their latency to be rewritten fastest-first or as a document
+    latencies: Option<Mutex<Vec<(Duration, String)>>>,
+    sort: bool,
+    // urls of saved proxies, a scheme-less line may be detected as a proxy listed with a scheme
+    proxies: Mutex<HashSet<String>>,
+    /// Proxies saved by this run.
+    pub saved: AtomicUsize,
+}
+
+impl ValidList {
+    /// Creates the output, or appends to it when resuming from `state`.
+    pub async fn open(
+        path: &str,
+        format: OutputFormat,
+        sort: bool,
+        state: Option<&State>,
+    ) -> Result<Self, IoError> {
+        let mut file = match state {
+            Some(_) => {
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?
+            }
+            None => fs::File::create(path).await?,
+        };
+        // csv header is written once, resumed runs append to it
+        if let Some(header) = format.header() {
+            if file.metadata().await?.len() == 0 {
+                file.write_all([header, "\n"].concat().as_bytes()).await?;
+            }
+        }
+
+        // lines recorded to the state but not written before a crash are restored,
+        // rewritten outputs have them anyway
+        let rewritten = sort || format.is_document();
+        let saved = state.map_or(&[][..], |state| &state.saved);
+        if state.is_some() && !rewritten {
+            let written = fs::read_to_string(path).await?;
+            let written = written.lines().collect::<HashSet<_>>();
+            let missing = saved
+                .iter()
+                .filter(|saved| !written.contains(saved.line.as_str()))
+                .map(|saved| [&saved.line, "\n"].concat())
+                .collect::<String>();
+            file.write_all(missing.as_bytes()).await?;
+        }
+        let latencies = if rewritten {
+            let saved = saved.iter();
+            Some(Mutex::new(
+                saved
+                    .map(|saved| (saved.latency, [&saved.line, "\n"].concat()))
+                    .collect(),
+            ))
+        } else {
+            None
+        };
+
+        Ok(ValidList {
+            file: Mutex::new(file),
+            format,
+            latencies,
+            sort,
+            proxies: Mutex::new(saved.iter().map(|saved| saved.proxy.clone()).collect()),
+            saved: AtomicUsize::new(0),
+        })
+    }
+
+    /// Whether the proxy wasn't saved yet, it's counted as saved from now on.
+    #[inline]
+    async fn claim(&self, proxy: &str) -> bool {
+        self.proxies.lock().await.insert(proxy.into())
+    }
+
+    #[inline]
+    async fn push(&self, saved: &Saved) -> Result<(), IoError> {
+        let line = [&saved.line, "\n"].concat();
+        self.file.lock().await.write_all(line.as_bytes()).await?;
+        self.saved.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(latencies) = &self.latencies {
+            latencies.lock().await.push((saved.latency, line));
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the output, rewriting it sorted by latency if it was requested
+    /// and wrapping into a document for formats which need it.
+    pub async fn finish(&self, path: &str) -> Result<(), IoError> {
+        let mut file = self.file.lock().await;
+
+        if let Some(latencies) = &self.latencies {
+            let mut latencies = latencies.lock().await;
+            if self.sort {
+                latencies.sort_by_key(|(latency, _)| *latency);
+            }
+
+            let lines = latencies.iter().map(|(_, line)| line.trim_end());
+            file.flush().await?;
+            *file = fs::File::create(path).await?;
+            file.write_all(self.format.document(lines).as_bytes())
+                .await?;
+        }
+
+        file.flush().await
+    }
+}
+
+pub struct SaveError(String, IoError);
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to save `{}`: {}", self.0, self.1)
+    }
+}
+
+/// Everything results of checks are written to.
+pub struct Outputs<'a> {
+    pub cfg: &'a AppConfig,
+    pub valid_list: &'a ValidList,
+    pub failed_list: Option<&'a FailedList>,
+    pub state_log: Option<&'a StateLog>,
+    pub stats: &'a Stats,
+}
+
+impl Outputs<'_> {
+    /// Writes the result everywhere it goes, failures are reported and skipped.
+    pub async fn save(&self, result: CheckResult) {
+        if let Err(err) = self.try_save(result).await {
+            eprintln!("{}", err);
+        }
+    }
+
+    async fn try_save(&self, result: CheckResult) -> Result<(), SaveError> {
+        let cfg = self.cfg;
+        let CheckResult { entry, checks } = result;
+        self.stats.checked.fetch_add(1, Ordering::Relaxed);
+
+        let mut valid = false;
+        let mut saved = Vec::new();
+        for ProtocolCheck { proxy, result } in checks {
+            let passed = match result {
+                Ok(passed) => passed,
+                Err(rejection) => {
+                    self.stats.record_check(&proxy, rejection.outcome, None);
+                    if let Some(failed_list) = self.failed_list {
+                        failed_list
+                            .push(&proxy, &rejection)
+                            .await
+                            .map_err(|err| SaveError(proxy, err))?;
+                    }
+                    continue;
+                }
+            };
+
+            self.stats
+                .record_check(&proxy, CheckOutcome::Valid, Some(passed.total_time));
+            valid = true;
+            if !self.valid_list.claim(&proxy).await {
+                continue;
+            }
+            let checked_at = passed
+                .checked_at
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs());
+            let line = ValidProxy {
+                proxy: &proxy,
+                anonymity: passed.anonymity,
+                exit_ip: passed.exit_ip,
+                connect_time: passed.connect_time,
+                total_time: passed.total_time,
+                attempts: passed.attempts,
+                checked_at,
+                target: &cfg.target,
+                source: &entry.source,
+                extra: &entry.extra,
+            }
+            .render(cfg.output_format);
+
+            saved.push(Saved {
+                proxy,
+                latency: passed.total_time,
+                exit_ip: passed.exit_ip,
+                line,
+            });
+        }
+
+        if valid {
+            self.stats.valid.fetch_add(1, Ordering::Relaxed);
+        }
+        self.stats.sources.record(Arc::clone(&entry.source), valid);
+        // state goes first, its lines missing from the output are restored on resume
+        if let Some(state_log) = self.state_log {
+            state_log
+                .record(&entry.proxy, &saved)
+                .await
+                .map_err(|err| SaveError(entry.proxy.clone(), err))?;
+        }
+        for valid in &saved {
+            self.valid_list
+                .push(valid)
+                .await
+                .map_err(|err| SaveError(entry.proxy.clone(), err))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_a_line_per_proxy() {
+        let target = Url::parse("http://example.com/").unwrap();
+        let mut extra = BTreeMap::new();
+        extra.insert("country".to_owned(), "US".to_owned());
+        extra.insert("note".to_owned(), "first\r\nsecond".to_owned());
+        let proxy = ValidProxy {
+            proxy: "http://1.2.3.4:80",
+            anonymity: Some(Anonymity::Elite),
+            exit_ip: Some("5.6.7.8".parse().unwrap()),
+            connect_time: Duration::from_millis(10),
+            total_time: Duration::from_millis(25),
+            attempts: 1,
+            checked_at: 1_600_000_000,
+            target: &target,
+            source: "list.txt",
+            extra: &extra,
+        };
+
+        assert_eq!(proxy.render(OutputFormat::Txt), "http://1.2.3.4:80 elite");
+        assert_eq!(
+            proxy.render(OutputFormat::Csv),
+            "http://1.2.3.4:80,http,1.2.3.4,80,elite,5.6.7.8,10,25,1,1600000000,\
+             http://example.com/,list.txt,country=US;note=first  second"
+        );
+        let json = proxy.render(OutputFormat::Jsonl);
+        assert!(!json.contains('\n'));
+        let json = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(json["extra"]["note"], "first\r\nsecond");
+        assert_eq!(json["port"], 80);
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+use tokio::{sync::Notify, time};
+use proxy_find::CheckOutcome;
+use crate::stats::Stats;
+
+/// Amount of failure reasons shown, the most frequent ones.
+const TOP_FAILURES: usize = 3;
+
+/// Shows the progress of checks until `stop` is notified.
+///
+/// A single line is redrawn on a terminal, otherwise plain lines are logged periodically.
+pub async fn show(stats: Arc<Stats>, stop: Arc<Notify>) {
+    let tty = io::stdout().is_terminal();
+    let interval = if tty {
+        Duration::from_millis(200)
+    } else {
+        Duration::from_secs(5)
+    };
+    let started = Instant::now();
+
+    loop {
+        let line = render(&stats, started.elapsed());
+        if tty {
+            let mut stdout = io::stdout();
+            let _ = write!(stdout, "\r\x1b[K{}", line);
+            let _ = stdout.flush();
+        } else {
+            println!("{}", line);
+        }
+
+        tokio::select! {
+            _ = time::delay_for(interval) => {}
+            _ = stop.notified() => break,
+        }
+    }
+
+    // the last state stays on a terminal
+    if tty {
+        let line = render(&stats, started.elapsed());
+        let mut stdout = io::stdout();
+        let _ = writeln!(stdout, "\r\x1b[K{}", line);
+        let _ = stdout.flush();
+    }
+}
+
+#[inline]
+fn render(stats: &Stats, elapsed: Duration) -> String {
+    let read = stats.read.load(Ordering::Relaxed);
+    let checked = stats.checked.load(Ordering::Relaxed);
+    let valid = stats.valid.load(Ordering::Relaxed);
+    let outcomes = stats.outcomes.get();
+
+    let mut line = if stats.read_all.load(Ordering::Relaxed) {
+        let percent = if read == 0 {
+            100.0
+        } else {
+            checked as f64 * 100.0 / read as f64
+        };
+        format!("Checked {}/{} ({:.1}%)", checked, read, percent)
+    } else {
+        format!("Checked {}/{}+", checked, read)
+    };
+
+    let success = if checked == 0 {
+        0.0
+    } else {
+        valid as f64 * 100.0 / checked as f64
+    };
+    let rate = checked as f64 / elapsed.as_secs_f64().max(0.001);
+    line.push_str(&format!(
+        ", valid {} ({:.1}%), {:.1}/s",
+        valid, success, rate
+    ));
+
+    let eta = match stats.read_all.load(Ordering::Relaxed) {
+        true if rate > 0.0 => format_secs(read.saturating_sub(checked) as f64 / rate),
+        _ => "?".into(),
+    };
+    line.push_str(&format!(", ETA {}", eta));
+
+    let mut failures = outcomes
+        .into_iter()
+        .filter(|(outcome, _)| *outcome != CheckOutcome::Valid)
+        .collect::<Vec<_>>();
+    failures.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    if !failures.is_empty() {
+        let failures = failures
+            .iter()
+            .take(TOP_FAILURES)
+            .map(|(outcome, count)| format!("{} {}", outcome.as_str(), count))
+            .collect::<Vec<_>>();
+        line.push_str(&format!(" | {}", failures.join(", ")));
+    }
+
+    line
+}
+
+#[inline]
+fn format_secs(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+use serde_json::{json, Map, Value};
+use tokio::{fs, io::Error as IoError};
+use proxy_find::{
+    normalize::{DropReason, Dropped},
+    CheckOutcome,
+};
+use crate::stats::Stats;
+
+/// Amount of ports printed to the console, the most checked ones.
+const TOP_PORTS: usize = 10;
+
+/// Statistics of a finished run.
+pub struct Report {
+    pub elapsed: Duration,
+    /// Proxies checked to the end.
+    pub checked: usize,
+    /// Checks of every protocol of the proxies, with their outcomes.
+    pub outcomes: Vec<(CheckOutcome, usize)>,
+    /// `p50`, `p90` and `p99` latencies of valid proxies.
+    pub latency: Option<[Duration; 3]>,
+    pub schemes: Vec<(Box<str>, usize, usize)>,
+    pub ports: Vec<(u16, usize, usize)>,
+    pub sources: Vec<(Arc<str>, usize, usize)>,
+    pub dropped: Vec<(DropReason, usize)>,
+}
+
+impl Report {
+    pub fn new(stats: &Stats, dropped: &Dropped, elapsed: Duration) -> Self {
+        let mut latencies = stats.latencies.lock().unwrap().clone();
+        latencies.sort();
+        let latency = if latencies.is_empty() {
+            None
+        } else {
+            Some([50.0, 90.0, 99.0].map(|p| percentile(&latencies, p)))
+        };
+
+        Report {
+            elapsed,
+            checked: stats.checked.load(Ordering::Relaxed),
+            outcomes: stats.outcomes.get(),
+            latency,
+            schemes: stats.schemes.get(),
+            ports: stats.ports.get(),
+            sources: stats.sources.get(),
+            dropped: dropped.get(),
+        }
+    }
+
+    /// Amount of `(total, valid, invalid)` checks.
+    pub fn totals(&self) -> (usize, usize, usize) {
+        let total = self.outcomes.iter().map(|(_, count)| count).sum();
+        let valid = self
+            .outcomes
+            .iter()
+            .find(|(outcome, _)| *outcome == CheckOutcome::Valid)
+            .map_or(0, |(_, count)| *count);
+
+        (total, valid, total - valid)
+    }
+
+    /// Checks per second over the whole run.
+    pub fn throughput(&self) -> f64 {
+        self.checked as f64 / self.elapsed.as_secs_f64().max(0.001)
+    }
+
+    pub fn print(&self) {
+        let dropped = self
+            .dropped
+            .iter()
+            .map(|(reason, count)| format!("{} `{}`", reason.as_str(), count))
+            .collect::<Vec<_>>();
+        println!("Dropped input lines: {}", dropped.join(", "));
+
+        let (total, valid, invalid) = self.totals();
+        println!(
+            "Checks: total `{}`, valid `{}`, invalid `{}`",
+            total, valid, invalid
+        );
+        let failures = self
+            .outcomes
+            .iter()
+            .filter(|(outcome, _)| *outcome != CheckOutcome::Valid)
+            .map(|(outcome, count)| format!("{} `{}`", outcome.as_str(), count))
+            .collect::<Vec<_>>();
+        if !failures.is_empty() {
+            println!("Failures: {}", failures.join(", "));
+        }
+        if let Some([p50, p90, p99]) = self.latency {
+            println!(
+                "Latency: p50 `{}ms`, p90 `{}ms`, p99 `{}ms`",
+                p50.as_millis(),
+                p90.as_millis(),
+                p99.as_millis()
+            );
+        }
+
+        for (scheme, checked, valid) in &self.schemes {
+            println!(
+                "Scheme `{}`: checked `{}`, valid `{}`",
+                scheme, checked, valid
+            );
+        }
+        let mut ports = self.ports.clone();
+        ports.sort_by_key(|(port, checked, _)| (std::cmp::Reverse(*checked), *port));
+        for (port, checked, valid) in ports.iter().take(TOP_PORTS) {
+            println!("Port `{}`: checked `{}`, valid `{}`", port, checked, valid);
+        }
+        if ports.len() > TOP_PORTS {
+            println!("...and `{}` more ports", ports.len() - TOP_PORTS);
+        }
+        for (source, checked, valid) in &self.sources {
+            println!("`{}`: checked `{}`, valid `{}`", source, checked, valid);
+        }
+
+        println!(
+            "Took `{:.1}s`, `{:.1}` proxies per second",
+            self.elapsed.as_secs_f64(),
+            self.throughput()
+        );
+    }
+
+    pub fn to_json(&self) -> Value {
+        let (total, valid, invalid) = self.totals();
+        let failures = self
+            .outcomes
+            .iter()
+            .filter(|(outcome, _)| *outcome != CheckOutcome::Valid)
+            .map(|(outcome, count)| (outcome.as_str().to_owned(), json!(count)))
+            .collect::<Map<_, _>>();
+        let dropped = self
+            .dropped
+            .iter()
+            .map(|(reason, count)| (reason.as_str().to_owned(), json!(count)))
+            .collect::<Map<_, _>>();
+        let latency = self.latency.map(|[p50, p90, p99]| {
+            json!({
+                "p50_ms": p50.as_millis() as u64,
+                "p90_ms": p90.as_millis() as u64,
+                "p99_ms": p99.as_millis() as u64,
+            })
+        });
+
+        json!({
+            "elapsed_secs": self.elapsed.as_secs_f64(),
+            "throughput": self.throughput(),
+            "proxies": self.checked,
+            "checks": {
+                "total": total,
+                "valid": valid,
+                "invalid": invalid,
+            },
+            "failures": failures,
+            "latency": latency,
+            "schemes": tally(&self.schemes),
+            "ports": tally(&self.ports),
+            "sources": tally(&self.sources),
+            "dropped": dropped,
+        })
+    }
+
+    pub async fn write(&self, path: &str) -> Result<(), IoError> {
+        let mut json = serde_json::to_string_pretty(&self.to_json())?;
+        json.push('\n');
+
+        fs::write(path, json).await
+    }
+}
+
+/// Nearest-rank percentile of sorted values.
+#[inline]
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[inline]
+fn tally<K: ToString>(counts: &[(K, usize, usize)]) -> Value {
+    let counts = counts
+        .iter()
+        .map(|(key, checked, valid)| {
+            let counts = json!({ "checked": checked, "valid": valid });
+            (key.to_string(), counts)
+        })
+        .collect::<Map<_, _>>();
+
+    Value::Object(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_nearest_rank_percentile() {
+        let sorted = (1..=10).map(Duration::from_secs).collect::<Vec<_>>();
+        assert_eq!(percentile(&sorted, 0.0), Duration::from_secs(1));
+        assert_eq!(percentile(&sorted, 50.0), Duration::from_secs(5));
+        assert_eq!(percentile(&sorted, 95.0), Duration::from_secs(10));
+        assert_eq!(percentile(&sorted, 100.0), Duration::from_secs(10));
+
+        let single = [Duration::from_millis(7)];
+        assert_eq!(percentile(&single, 50.0), Duration::from_millis(7));
+    }
+}
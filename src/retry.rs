@@ -0,0 +1,69 @@
+use std::time::Duration;
+use rand::Rng;
+
+/// Max delay before a retry, however many attempts failed.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How failed checks are retried.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// Max amount of retries after the first attempt.
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every next one.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Delay before retrying after `attempt` (starting from 1) failed, with up to 50% of jitter.
+    ///
+    /// The delay is capped by `MAX_BACKOFF` before the jitter is added.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .backoff
+            .checked_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF));
+        let jitter = rand::thread_rng().gen_range(0..=delay.as_millis() as u64 / 2);
+
+        delay + Duration::from_millis(jitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_delay_with_jitter() {
+        let policy = RetryPolicy {
+            retries: 3,
+            backoff: Duration::from_millis(100),
+        };
+        for (attempt, delay) in [(1, 100), (2, 200), (3, 400)] {
+            let delay = Duration::from_millis(delay);
+            for _ in 0..100 {
+                let jittered = policy.delay(attempt);
+                assert!(jittered >= delay && jittered <= delay * 3 / 2);
+            }
+        }
+    }
+
+    #[test]
+    fn caps_delay() {
+        let policy = RetryPolicy {
+            retries: 40,
+            backoff: Duration::from_millis(500),
+        };
+        for attempt in [8, 40, u32::MAX] {
+            let delay = policy.delay(attempt);
+            assert!(delay >= MAX_BACKOFF && delay <= MAX_BACKOFF * 3 / 2);
+        }
+
+        // the multiplication itself would overflow
+        let policy = RetryPolicy {
+            retries: 1,
+            backoff: Duration::from_millis(u64::MAX),
+        };
+        assert!(policy.delay(1) <= MAX_BACKOFF * 3 / 2);
+        assert!(policy.delay(2) <= MAX_BACKOFF * 3 / 2);
+    }
+}
@@ -0,0 +1,181 @@
+use std::net::{IpAddr, Ipv4Addr};
+use tokio::{
+    io::{
+        AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Error as IoError,
+        ErrorKind as IoErrorKind,
+    },
+    net,
+};
+
+/// Socks4 proxy, `tokio-socks` speaks only socks5.
+pub struct Socks4 {
+    pub user_id: String,
+    /// Socks4a: target hostname is resolved by the proxy.
+    pub remote_dns: bool,
+}
+
+impl Socks4 {
+    /// Opens a tunnel to `host:port` over a connection to the proxy.
+    pub async fn handshake<S>(&self, stream: &mut S, host: &str, port: u16) -> Result<(), IoError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let ip = match host.parse::<Ipv4Addr>() {
+            Ok(ip) => Some(ip),
+            Err(_) if self.remote_dns => None,
+            Err(_) => Some(resolve(host, port).await?),
+        };
+
+        let mut request = vec![0x04, 0x01];
+        request.extend_from_slice(&port.to_be_bytes());
+        // socks4a marks remote resolving by an invalid ip `0.0.0.x` and appends the hostname
+        request.extend_from_slice(&ip.unwrap_or_else(|| Ipv4Addr::new(0, 0, 0, 1)).octets());
+        request.extend_from_slice(self.user_id.as_bytes());
+        request.push(0x00);
+        if ip.is_none() {
+            request.extend_from_slice(host.as_bytes());
+            request.push(0x00);
+        }
+
+        stream.write_all(&request).await?;
+
+        let mut reply = [0; 8];
+        stream.read_exact(&mut reply).await?;
+
+        match reply[1] {
+            _ if reply[0] != 0x00 => Err(IoError::new(
+                IoErrorKind::InvalidData,
+                "invalid socks4 reply",
+            )),
+            0x5A => Ok(()),
+            0x5C | 0x5D => Err(IoError::new(
+                IoErrorKind::PermissionDenied,
+                "socks4 request rejected by identd",
+            )),
+            _ => Err(IoError::new(
+                IoErrorKind::ConnectionRefused,
+                "socks4 request rejected",
+            )),
+        }
+    }
+}
+
+#[inline]
+async fn resolve(host: &str, port: u16) -> Result<Ipv4Addr, IoError> {
+    net::lookup_host((host, port))
+        .await?
+        .find_map(|addr| match addr.ip() {
+            IpAddr::V4(ip) => Some(ip),
+            IpAddr::V6(_) => None,
+        })
+        .ok_or_else(|| IoError::new(IoErrorKind::NotFound, "host has no ipv4 address"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use tokio::{
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    };
+    use super::*;
+
+    /// Answers a single request of `len` bytes with `reply`, the request is returned by the handle.
+    async fn serve(len: usize, reply: [u8; 8]) -> (SocketAddr, JoinHandle<Vec<u8>>) {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let request = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; len];
+            stream.read_exact(&mut request).await.unwrap();
+            stream.write_all(&reply).await.unwrap();
+            request
+        });
+
+        (addr, request)
+    }
+
+    /// Sends a handshake for `host:port` and returns its result with the bytes the proxy got.
+    async fn handshake(
+        proxy: Socks4,
+        host: &str,
+        port: u16,
+        expected: usize,
+        reply: [u8; 8],
+    ) -> (Result<(), IoError>, Vec<u8>) {
+        let (addr, request) = serve(expected, reply).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let result = proxy.handshake(&mut stream, host, port).await;
+
+        (result, request.await.unwrap())
+    }
+
+    const GRANTED: [u8; 8] = [0x00, 0x5A, 0, 0, 0, 0, 0, 0];
+
+    #[tokio::test]
+    async fn sends_socks4_request() {
+        let proxy = Socks4 {
+            user_id: "user".into(),
+            remote_dns: false,
+        };
+        let expected = [&[0x04, 0x01, 0x00, 0x50, 1, 2, 3, 4][..], b"user\0"].concat();
+
+        let (result, request) = handshake(proxy, "1.2.3.4", 80, expected.len(), GRANTED).await;
+        assert!(result.is_ok());
+        assert_eq!(request, expected);
+    }
+
+    #[tokio::test]
+    async fn sends_socks4a_request() {
+        let socks4a = || Socks4 {
+            user_id: "user".into(),
+            remote_dns: true,
+        };
+        // hostname follows the user id, the ip is the `0.0.0.1` marker
+        let expected = [
+            &[0x04, 0x01, 0x01, 0xBB, 0, 0, 0, 1][..],
+            b"user\0example.com\0",
+        ]
+        .concat();
+
+        let (result, request) =
+            handshake(socks4a(), "example.com", 443, expected.len(), GRANTED).await;
+        assert!(result.is_ok());
+        assert_eq!(request, expected);
+
+        // ips are sent as they are
+        let expected = [&[0x04, 0x01, 0x01, 0xBB, 1, 2, 3, 4][..], b"user\0"].concat();
+        let (result, request) = handshake(socks4a(), "1.2.3.4", 443, expected.len(), GRANTED).await;
+        assert!(result.is_ok());
+        assert_eq!(request, expected);
+    }
+
+    #[tokio::test]
+    async fn rejects_by_reply() {
+        let replies = [
+            (0x5B, IoErrorKind::ConnectionRefused),
+            (0x5C, IoErrorKind::PermissionDenied),
+            (0x5D, IoErrorKind::PermissionDenied),
+        ];
+        for (code, kind) in replies.iter() {
+            let proxy = Socks4 {
+                user_id: String::new(),
+                remote_dns: false,
+            };
+            let reply = [0x00, *code, 0, 0, 0, 0, 0, 0];
+
+            let (result, _) = handshake(proxy, "1.2.3.4", 80, 9, reply).await;
+            assert_eq!(result.unwrap_err().kind(), *kind, "{:#x}", code);
+        }
+
+        // socks5 and http proxies don't answer with a null byte first
+        let proxy = Socks4 {
+            user_id: String::new(),
+            remote_dns: false,
+        };
+        let reply = [0x05, 0x5A, 0, 0, 0, 0, 0, 0];
+        let (result, _) = handshake(proxy, "1.2.3.4", 80, 9, reply).await;
+        assert_eq!(result.unwrap_err().kind(), IoErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,325 @@
+use std::{error::Error, fmt, str::FromStr, sync::Arc};
+use futures::future;
+use regex::Regex;
+use reqwest::Url;
+use serde_json::Value;
+use crate::{
+    client::{BoxError, ProxyClient},
+    normalize::DropReason,
+    parse::{self, InputFormat, ProxyRecord},
+};
+
+/// How proxies are picked out of a fetched list.
+pub enum Parser {
+    /// Proxy per line in any line based input format.
+    Plain,
+    /// Every match is a proxy. Named groups `host` (or `ip`) and `port` are used if present,
+    /// otherwise the first group or the whole match.
+    Regex(Regex),
+    /// Dot separated path to proxies in a json document, `*` walks every array item.
+    Json(Vec<Box<str>>),
+}
+
+/// Url of a public proxy list.
+pub struct ListSource {
+    pub url: Url,
+    pub parser: Parser,
+}
+
+impl FromStr for ListSource {
+    type Err = Box<dyn Error>;
+
+    /// Parses `<url> [plain | regex <pattern> | json <path>]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(3, char::is_whitespace);
+        let url = parts.next().unwrap_or_default().parse()?;
+        let parser = match (parts.next(), parts.next().map(str::trim)) {
+            (None, _) | (Some("plain"), None) => Parser::Plain,
+            (Some("regex"), Some(pattern)) => Parser::Regex(Regex::new(pattern)?),
+            (Some("json"), Some(path)) => Parser::Json(path.split('.').map(Into::into).collect()),
+            _ => return Err(format!("Invalid source `{}`", s.trim()).into()),
+        };
+
+        Ok(ListSource { url, parser })
+    }
+}
+
+/// Loads list sources, one per line, skipping blank lines and `#` comments.
+pub fn load(path: &str) -> Result<Vec<ListSource>, Box<dyn Error>> {
+    let list = std::fs::read_to_string(path)?;
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+/// Proxies fetched from a list source.
+#[derive(Debug)]
+pub struct Fetched {
+    pub url: Arc<str>,
+    pub records: Vec<Result<ProxyRecord, DropReason>>,
+}
+
+/// List source which failed to be fetched.
+#[derive(Debug)]
+pub struct FetchError {
+    pub url: Url,
+    pub error: BoxError,
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to fetch `{}`: {}", self.url, self.error)
+    }
+}
+
+impl Error for FetchError {}
+
+/// Fetches all the sources concurrently.
+pub async fn fetch_all(
+    sources: Vec<ListSource>,
+    client: ProxyClient,
+) -> Vec<Result<Fetched, FetchError>> {
+    let client = &client;
+    let fetches = sources.into_iter().map(|source| async move {
+        match fetch(&source, client).await {
+            Ok(records) => Ok(Fetched {
+                url: source.url.as_str().into(),
+                records,
+            }),
+            Err(error) => Err(FetchError {
+                url: source.url,
+                error,
+            }),
+        }
+    });
+
+    future::join_all(fetches).await
+}
+
+#[inline]
+async fn fetch(
+    source: &ListSource,
+    client: &ProxyClient,
+) -> Result<Vec<Result<ProxyRecord, DropReason>>, BoxError> {
+    let reply = client.get(&source.url).await?;
+    if !reply.status.is_success() {
+        return Err(format!("status `{}`", reply.status).into());
+    }
+    let body = reply.body.ok_or("failed to read body")?;
+
+    let records = match &source.parser {
+        Parser::Plain => body.lines().map(parse_plain_line).collect(),
+        Parser::Regex(pattern) => pattern
+            .captures_iter(&body)
+            .map(|captures| {
+                let host = captures.name("host").or_else(|| captures.name("ip"));
+                let record = match (host, captures.name("port")) {
+                    (Some(host), Some(port)) => {
+                        parse::parse_plain(&format!("{}:{}", host.as_str(), port.as_str()))
+                    }
+                    _ => {
+                        let proxy = captures.get(1).or_else(|| captures.get(0)).unwrap();
+                        parse::parse_plain(proxy.as_str())
+                    }
+                };
+                record.ok_or(DropReason::Invalid)
+            })
+            .collect(),
+        Parser::Json(path) => {
+            let json = serde_json::from_str(&body)?;
+            let mut values = Vec::new();
+            select(json, path, &mut values);
+            values
+                .into_iter()
+                .map(|value| parse::parse_json(value).ok_or(DropReason::Invalid))
+                .collect()
+        }
+    };
+
+    Ok(records)
+}
+
+#[inline]
+fn parse_plain_line(line: &str) -> Result<ProxyRecord, DropReason> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Err(DropReason::Blank);
+    }
+    if line.starts_with('#') {
+        return Err(DropReason::Comment);
+    }
+
+    parse::parse_line(InputFormat::Auto, line).ok_or(DropReason::Invalid)
+}
+
+/// Collects values at the json path, missing keys select nothing.
+fn select(value: Value, path: &[Box<str>], values: &mut Vec<Value>) {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => {
+            values.push(value);
+            return;
+        }
+    };
+
+    match (value, key.as_ref()) {
+        (Value::Array(items), "*") => {
+            for item in items {
+                select(item, rest, values);
+            }
+        }
+        (Value::Object(mut fields), key) => {
+            if let Some(field) = fields.remove(key) {
+                select(field, rest, values);
+            }
+        }
+        (Value::Array(mut items), index) => {
+            if let Some(idx) = index.parse().ok().filter(|idx| *idx < items.len()) {
+                select(items.swap_remove(idx), rest, values);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use super::*;
+
+    /// Serves `(path, status, body)` over http until the test ends, returns the base url.
+    async fn serve(pages: Vec<(&'static str, u16, &'static str)>) -> String {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..read]);
+                }
+
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let (status, body) = pages
+                    .iter()
+                    .find(|(page, _, _)| *page == path)
+                    .map_or((404, ""), |(_, status, body)| (*status, *body));
+                let response = format!(
+                    "HTTP/1.1 {} OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        format!("http://{}", addr)
+    }
+
+    fn urls(fetched: &Fetched) -> Vec<Result<String, DropReason>> {
+        let records = fetched.records.iter();
+        records
+            .map(|record| record.as_ref().map(ProxyRecord::url).map_err(|x| *x))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn fetches_every_list_format() {
+        let base = serve(vec![
+            (
+                "/plain",
+                200,
+                "1.2.3.4:80\n# comment\n\nsocks5://5.6.7.8:1080\n:8080\n",
+            ),
+            (
+                "/regex",
+                200,
+                "<td>1.2.3.4</td><td>8080</td>\n<td>9.9.9.9</td><td>3128</td>",
+            ),
+            (
+                "/json",
+                200,
+                r#"{"data": [{"ip": "1.2.3.4", "port": 80}, {"proxy": "5.6.7.8:3128"}]}"#,
+            ),
+            ("/error", 500, "1.2.3.4:80"),
+        ])
+        .await;
+        let sources = [
+            format!("{}/plain", base),
+            format!(
+                r"{}/regex regex <td>(?P<ip>[\d.]+)</td><td>(?P<port>\d+)</td>",
+                base
+            ),
+            format!("{}/json json data.*", base),
+            format!("{}/error", base),
+            format!("{}/missing", base),
+        ];
+        let sources = sources.iter().map(|x| x.parse().unwrap()).collect();
+        let client = ProxyClient::new(5, None).unwrap();
+
+        let fetched = fetch_all(sources, client).await;
+        assert_eq!(fetched.len(), 5);
+
+        let plain = fetched[0].as_ref().unwrap();
+        assert_eq!(plain.url.as_ref(), format!("{}/plain", base));
+        assert_eq!(
+            urls(plain),
+            vec![
+                Ok("1.2.3.4:80".into()),
+                Err(DropReason::Comment),
+                Err(DropReason::Blank),
+                Ok("socks5://5.6.7.8:1080".into()),
+                Err(DropReason::Invalid),
+            ]
+        );
+        assert_eq!(
+            urls(fetched[1].as_ref().unwrap()),
+            vec![Ok("1.2.3.4:8080".into()), Ok("9.9.9.9:3128".into())]
+        );
+        assert_eq!(
+            urls(fetched[2].as_ref().unwrap()),
+            vec![Ok("1.2.3.4:80".into()), Ok("5.6.7.8:3128".into())]
+        );
+
+        let error = fetched[3].as_ref().unwrap_err();
+        assert!(error.to_string().contains("status `500"));
+        let missing = fetched[4].as_ref().unwrap_err();
+        assert!(missing.to_string().contains("status `404"));
+    }
+
+    #[test]
+    fn selects_values_by_path() {
+        let path = |path: &str| path.split('.').map(Into::into).collect::<Vec<_>>();
+        let json = json!({
+            "data": [{"proxy": "a"}, {"proxy": "b"}, {"other": "c"}],
+            "nested": {"list": ["x", "y"]},
+        });
+
+        let mut values = Vec::new();
+        select(json.clone(), &path("data.*.proxy"), &mut values);
+        assert_eq!(values, vec![json!("a"), json!("b")]);
+
+        let mut values = Vec::new();
+        select(json.clone(), &path("nested.list.1"), &mut values);
+        assert_eq!(values, vec![json!("y")]);
+
+        let mut values = Vec::new();
+        select(json.clone(), &path("nested.list.5"), &mut values);
+        select(json, &path("missing.*"), &mut values);
+        assert!(values.is_empty());
+    }
+}
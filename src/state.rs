@@ -0,0 +1,198 @@
+use std::{collections::HashSet, net::IpAddr, time::Duration};
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Error as IoError, ErrorKind as IoErrorKind},
+    sync::Mutex,
+};
+
+/// Proxy saved to the output.
+pub struct Saved {
+    /// Proxy url with the protocol it passed with.
+    pub proxy: String,
+    pub latency: Duration,
+    pub exit_ip: Option<IpAddr>,
+    /// Output line without the trailing newline.
+    pub line: String,
+}
+
+/// Checks finished by previous runs.
+#[derive(Default)]
+pub struct State {
+    pub checked: HashSet<String>,
+    pub saved: Vec<Saved>,
+}
+
+/// Loads the state file, a missing file is an empty state.
+///
+/// Every line is `<entry>\tinvalid`
+/// or `<entry>\tvalid\t<latency ms>\t<exit ip or ->\t<proxy>\t<output line>`.
+pub async fn load(path: &str) -> Result<State, IoError> {
+    let file = match fs::File::open(path).await {
+        Ok(file) => file,
+        Err(err) if err.kind() == IoErrorKind::NotFound => return Ok(State::default()),
+        Err(err) => return Err(err),
+    };
+
+    let mut state = State::default();
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            break;
+        }
+        // last line may be cut by a crash, complete ones end with a newline
+        let line = match line.strip_suffix('\n') {
+            Some(line) => line,
+            None => break,
+        };
+
+        let mut fields = line.splitn(6, '\t');
+        let (entry, status) = match (fields.next(), fields.next()) {
+            (Some(entry), Some(status)) => (entry, status),
+            _ => continue,
+        };
+        match status {
+            "valid" => {
+                let saved = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                    (Some(latency), Some(exit_ip), Some(proxy), Some(line)) => Saved {
+                        proxy: proxy.into(),
+                        latency: Duration::from_millis(latency.parse().unwrap_or(u64::MAX)),
+                        exit_ip: exit_ip.parse().ok(),
+                        line: line.into(),
+                    },
+                    _ => continue,
+                };
+                state.saved.push(saved);
+            }
+            "invalid" => {}
+            _ => continue,
+        }
+
+        state.checked.insert(entry.into());
+    }
+
+    Ok(state)
+}
+
+/// Append-only log of finished checks.
+pub struct StateLog {
+    file: Mutex<fs::File>,
+}
+
+impl StateLog {
+    pub async fn open(path: &str) -> Result<Self, IoError> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        Ok(StateLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Records a checked entry with the proxies saved from it.
+    pub async fn record(&self, entry: &str, saved: &[Saved]) -> Result<(), IoError> {
+        let mut record = String::new();
+        for saved in saved {
+            let exit_ip = saved.exit_ip.map(|ip| ip.to_string());
+            record.push_str(&format!(
+                "{}\tvalid\t{}\t{}\t{}\t{}\n",
+                entry,
+                saved.latency.as_millis(),
+                exit_ip.as_deref().unwrap_or("-"),
+                saved.proxy,
+                saved.line
+            ));
+        }
+        if saved.is_empty() {
+            record.push_str(&format!("{}\tinvalid\n", entry));
+        }
+
+        self.file.lock().await.write_all(record.as_bytes()).await
+    }
+
+    pub async fn flush(&self) -> Result<(), IoError> {
+        self.file.lock().await.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path of a fresh file in the temp dir, unique per test.
+    async fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("proxy-find-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path).await;
+        path.to_string_lossy().into_owned()
+    }
+
+    #[tokio::test]
+    async fn loads_recorded_checks() {
+        let path = temp_path("state-round-trip").await;
+        let log = StateLog::open(&path).await.unwrap();
+        let saved = [
+            Saved {
+                proxy: "http://1.2.3.4:80".into(),
+                latency: Duration::from_millis(120),
+                exit_ip: Some("1.2.3.4".parse().unwrap()),
+                line: "http://1.2.3.4:80\twith\ttabs".into(),
+            },
+            Saved {
+                proxy: "socks5://1.2.3.4:80".into(),
+                latency: Duration::from_millis(80),
+                exit_ip: None,
+                line: "socks5://1.2.3.4:80".into(),
+            },
+        ];
+        log.record("1.2.3.4:80", &saved).await.unwrap();
+        log.record("5.6.7.8:80", &[]).await.unwrap();
+        log.flush().await.unwrap();
+
+        let state = load(&path).await.unwrap();
+        fs::remove_file(&path).await.unwrap();
+        assert_eq!(state.checked.len(), 2);
+        assert!(state.checked.contains("1.2.3.4:80"));
+        assert!(state.checked.contains("5.6.7.8:80"));
+        assert_eq!(state.saved.len(), 2);
+        for (loaded, saved) in state.saved.iter().zip(&saved) {
+            assert_eq!(loaded.proxy, saved.proxy);
+            assert_eq!(loaded.latency, saved.latency);
+            assert_eq!(loaded.exit_ip, saved.exit_ip);
+            assert_eq!(loaded.line, saved.line);
+        }
+    }
+
+    #[tokio::test]
+    async fn skips_cut_lines() {
+        let valid = "2.2.2.2:80\tvalid\t12\t-\thttp://2.2.2.2:80\thttp://2.2.2.2:80\n";
+        let cuts = [
+            "3.3.3.3:80\tinval",
+            "3.3.3.3:80\tvalid\t12\t-\thttp://3.3.3.3:80\thttp://3.3",
+            "3.3.3.3:80",
+        ];
+        for (i, cut) in cuts.iter().enumerate() {
+            let path = temp_path(&format!("state-cut-{}", i)).await;
+            let text = ["1.1.1.1:80\tinvalid\n", valid, cut].concat();
+            fs::write(&path, text).await.unwrap();
+
+            let state = load(&path).await.unwrap();
+            fs::remove_file(&path).await.unwrap();
+            let mut checked = state.checked.iter().collect::<Vec<_>>();
+            checked.sort();
+            assert_eq!(checked, ["1.1.1.1:80", "2.2.2.2:80"], "{}", cut);
+            assert_eq!(state.saved.len(), 1);
+            assert_eq!(state.saved[0].line, "http://2.2.2.2:80");
+        }
+    }
+
+    #[tokio::test]
+    async fn loads_missing_file_as_empty() {
+        let state = load(&temp_path("state-missing").await).await.unwrap();
+        assert!(state.checked.is_empty());
+        assert!(state.saved.is_empty());
+    }
+}
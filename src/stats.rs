@@ -0,0 +1,104 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc, Mutex as StdMutex,
+    },
+    time::Duration,
+};
+use proxy_find::CheckOutcome;
+
+/// Counters of the run, shared by the reader, the checks and the progress display.
+#[derive(Default)]
+pub struct Stats {
+    /// Proxies read from the input to be checked.
+    pub read: AtomicUsize,
+    /// Whether the whole input was read.
+    pub read_all: AtomicBool,
+    /// Proxies whose checks were started.
+    pub scheduled: AtomicUsize,
+    /// Proxies checked to the end.
+    pub checked: AtomicUsize,
+    /// Proxies which passed the check with any protocol.
+    pub valid: AtomicUsize,
+    pub sources: Tally<Arc<str>>,
+    pub schemes: Tally<Box<str>>,
+    pub ports: Tally<u16>,
+    pub outcomes: OutcomeStats,
+    /// Latencies of valid proxies.
+    pub latencies: StdMutex<Vec<Duration>>,
+}
+
+impl Stats {
+    /// Records a single check of the proxy, schemeless ones are counted under `-`.
+    pub fn record_check(&self, proxy: &str, outcome: CheckOutcome, latency: Option<Duration>) {
+        let valid = outcome == CheckOutcome::Valid;
+        let (scheme, rest) = proxy.split_once("://").unwrap_or(("-", proxy));
+        let authority = rest.split('/').next().unwrap_or_default();
+        let port = authority
+            .rsplit_once(':')
+            .and_then(|(_, port)| port.parse().ok());
+
+        self.outcomes.record(outcome);
+        self.schemes.record(scheme.into(), valid);
+        if let Some(port) = port {
+            self.ports.record(port, valid);
+        }
+        if let Some(latency) = latency {
+            self.latencies.lock().unwrap().push(latency);
+        }
+    }
+}
+
+/// Amount of checked and valid proxies per key.
+pub struct Tally<K> {
+    counts: StdMutex<BTreeMap<K, (usize, usize)>>,
+}
+
+impl<K> Default for Tally<K> {
+    fn default() -> Self {
+        Tally {
+            counts: StdMutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl<K: Ord + Clone> Tally<K> {
+    pub fn record(&self, key: K, valid: bool) {
+        let mut counts = self.counts.lock().unwrap();
+        let (checked, saved) = counts.entry(key).or_default();
+        *checked += 1;
+        if valid {
+            *saved += 1;
+        }
+    }
+
+    /// Returns `(key, checked, valid)` for every key.
+    pub fn get(&self) -> Vec<(K, usize, usize)> {
+        let counts = self.counts.lock().unwrap();
+        counts
+            .iter()
+            .map(|(key, (checked, valid))| (key.clone(), *checked, *valid))
+            .collect()
+    }
+}
+
+/// Amount of checks per outcome.
+#[derive(Default)]
+pub struct OutcomeStats {
+    stats: StdMutex<BTreeMap<CheckOutcome, usize>>,
+}
+
+impl OutcomeStats {
+    pub fn record(&self, outcome: CheckOutcome) {
+        *self.stats.lock().unwrap().entry(outcome).or_default() += 1;
+    }
+
+    pub fn get(&self) -> Vec<(CheckOutcome, usize)> {
+        let stats = self.stats.lock().unwrap();
+        stats
+            .iter()
+            .map(|(outcome, count)| (*outcome, *count))
+            .collect()
+    }
+}
@@ -0,0 +1,174 @@
+use std::{ops::RangeInclusive, str::FromStr};
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName},
+    StatusCode,
+};
+
+/// Rules the target response received through a proxy must match.
+#[derive(Clone, Default)]
+pub struct Rules {
+    /// Accepted status codes, any status is accepted when empty.
+    pub statuses: Vec<RangeInclusive<u16>>,
+    pub body_contains: Option<String>,
+    pub body_regex: Option<Regex>,
+    pub headers: Vec<HeaderRule>,
+}
+
+impl Rules {
+    /// Whether the body has to be read to match the response.
+    pub fn needs_body(&self) -> bool {
+        self.body_contains.is_some() || self.body_regex.is_some()
+    }
+
+    /// Matches the response status, run before the body is read.
+    pub fn matches_status(&self, status: StatusCode) -> bool {
+        let status = status.as_u16();
+
+        self.statuses.is_empty() || self.statuses.iter().any(|range| range.contains(&status))
+    }
+
+    pub fn matches_headers(&self, headers: &HeaderMap) -> bool {
+        self.headers.iter().all(|rule| rule.matches(headers))
+    }
+
+    pub fn matches_body(&self, body: &str) -> bool {
+        self.body_contains
+            .as_ref()
+            .is_none_or(|text| body.contains(text.as_str()))
+            && self
+                .body_regex
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(body))
+    }
+}
+
+/// Parses comma separated status codes and ranges like `200,204,300-399`.
+pub fn parse_statuses(s: &str) -> Result<Vec<RangeInclusive<u16>>, String> {
+    s.split(',')
+        .map(str::trim)
+        .map(|part| {
+            let (start, end) = match part.find('-') {
+                Some(idx) => (&part[..idx], &part[idx + 1..]),
+                None => (part, part),
+            };
+
+            match (start.trim().parse(), end.trim().parse()) {
+                (Ok(start), Ok(end)) if start <= end => Ok(start..=end),
+                _ => Err(format!("Invalid status `{}`", part)),
+            }
+        })
+        .collect()
+}
+
+/// Header which must be present in the response, with a value containing `value` if set.
+#[derive(Clone)]
+pub struct HeaderRule {
+    pub name: HeaderName,
+    pub value: Option<String>,
+}
+
+impl HeaderRule {
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        headers
+            .get_all(&self.name)
+            .iter()
+            .any(|value| match &self.value {
+                Some(expected) => value
+                    .to_str()
+                    .is_ok_and(|value| value.contains(expected.as_str())),
+                None => true,
+            })
+    }
+}
+
+impl FromStr for HeaderRule {
+    type Err = String;
+
+    /// Parses `Name` or `Name: value`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.find(':') {
+            Some(idx) => (&s[..idx], Some(s[idx + 1..].trim().to_owned())),
+            None => (s, None),
+        };
+
+        Ok(HeaderRule {
+            name: name
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid header name `{}`", name))?,
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+    use super::*;
+
+    #[test]
+    fn parses_statuses() {
+        assert_eq!(
+            parse_statuses("200, 204,300 - 399"),
+            Ok(vec![200..=200, 204..=204, 300..=399])
+        );
+        assert!(parse_statuses("399-300").is_err());
+        assert!(parse_statuses("200,").is_err());
+        assert_eq!(parse_statuses("2xx"), Err("Invalid status `2xx`".into()));
+    }
+
+    #[test]
+    fn matches_status() {
+        let rules = Rules::default();
+        assert!(rules.matches_status(StatusCode::NOT_FOUND));
+
+        let rules = Rules {
+            statuses: parse_statuses("200,300-399").unwrap(),
+            ..Rules::default()
+        };
+        assert!(rules.matches_status(StatusCode::OK));
+        assert!(rules.matches_status(StatusCode::FOUND));
+        assert!(!rules.matches_status(StatusCode::NO_CONTENT));
+    }
+
+    #[test]
+    fn matches_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("server", HeaderValue::from_static("nginx/1.18"));
+        headers.append("x-cache", HeaderValue::from_static("MISS"));
+        headers.append("x-cache", HeaderValue::from_static("HIT"));
+
+        let rules = |rules: &[&str]| Rules {
+            headers: rules.iter().map(|rule| rule.parse().unwrap()).collect(),
+            ..Rules::default()
+        };
+        assert!(rules(&[]).matches_headers(&headers));
+        assert!(rules(&["Server", "X-Cache: HIT"]).matches_headers(&headers));
+        assert!(rules(&["server:nginx"]).matches_headers(&headers));
+        assert!(!rules(&["server: apache"]).matches_headers(&headers));
+        assert!(!rules(&["via"]).matches_headers(&headers));
+
+        let rule = "Name : ".parse::<HeaderRule>().unwrap();
+        assert_eq!(rule.name, "name");
+        assert_eq!(rule.value.as_deref(), Some(""));
+        assert!("bad name: x".parse::<HeaderRule>().is_err());
+    }
+
+    #[test]
+    fn matches_body() {
+        let rules = Rules::default();
+        assert!(!rules.needs_body());
+        assert!(rules.matches_body(""));
+
+        let rules = Rules {
+            body_contains: Some("origin".into()),
+            body_regex: Some(Regex::new(r"\d+\.\d+\.\d+\.\d+").unwrap()),
+            ..Rules::default()
+        };
+        assert!(rules.needs_body());
+        assert!(rules.matches_body(r#"{"origin": "1.2.3.4"}"#));
+        assert!(!rules.matches_body(r#"{"origin": "unknown"}"#));
+        assert!(!rules.matches_body(r#"{"ip": "1.2.3.4"}"#));
+    }
+}